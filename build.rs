@@ -4,5 +4,6 @@ fn main() {
         .cargo_out_dir("protos")
         .include("src")
         .input("src/protocol/protos/idep.proto")
+        .input("src/protocol/protos/state.proto")
         .run_from_script();
 }
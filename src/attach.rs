@@ -0,0 +1,113 @@
+use crate::config::RemoteServer;
+#[cfg(feature = "tls")]
+use crate::protocol::streams::tls;
+use crate::protocol::streams::unix;
+use crate::protocol::{auth, tunnel, Client, ProtocolError, Receiver, Sender};
+use crate::Config;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+use tokio::select;
+
+/// Name the client introduces itself to server with.
+const CLIENT_NAME: &str = "ide";
+
+/// Opens editor attached to `project`, given by name or path, of the local
+/// server, or of the remote `server` listed in config. The project is served
+/// if it is idle. Editors of remote servers are reached through tunnel
+/// forwarded to a local socket. Returns when editor exits.
+pub async fn attach(cfg: &Config, project: &str, server: Option<&str>) -> io::Result<()> {
+    let Some(name) = server else {
+        let (s, r) = unix::connect(cfg.server_socket()).await?;
+        let client = Client::connect(s, r, CLIENT_NAME).await?;
+        return attach_to(cfg, client, project, None).await;
+    };
+    let Some(remote) = cfg.servers().iter().find(|srv| srv.name == name) else {
+        let err = format!("No server '{name}' in config");
+        return Err(io::Error::new(io::ErrorKind::NotFound, err));
+    };
+    let token = auth::read_token(&remote.token_file)?;
+    let folder = &cfg.headless().sockets_folder;
+    fs::create_dir_all(folder)?;
+    let local = folder.join(format!("attach-{}.sock", std::process::id()));
+    attach_remote(cfg, remote, &token, project, local).await
+}
+
+/// Remote servers are talked to over TLS, see `config::Listen`.
+#[cfg(feature = "tls")]
+async fn attach_remote(
+    cfg: &Config,
+    remote: &RemoteServer,
+    token: &str,
+    project: &str,
+    local: PathBuf,
+) -> io::Result<()> {
+    let config = tls::client_config(tls::load_certificates(&remote.ca_file)?, None)?;
+    let (s, r) = tls::connect(&remote.address, remote.host(), config).await?;
+    let client = Client::connect_with_token(s, r, CLIENT_NAME, token).await?;
+    attach_to(cfg, client, project, Some(local)).await
+}
+
+#[cfg(not(feature = "tls"))]
+async fn attach_remote(
+    _cfg: &Config,
+    remote: &RemoteServer,
+    _token: &str,
+    _project: &str,
+    _local: PathBuf,
+) -> io::Result<()> {
+    let err = format!("Attaching to '{}' requires tls feature", remote.name);
+    Err(io::Error::new(io::ErrorKind::Unsupported, err))
+}
+
+async fn attach_to<S: Sender, R: Receiver>(
+    cfg: &Config,
+    mut client: Client<S, R>,
+    project: &str,
+    forward_at: Option<PathBuf>,
+) -> io::Result<()> {
+    let mut requester = client.get_requester();
+    let attached = async {
+        let projects = requester.list_projects().await?;
+        let found = projects
+            .iter()
+            .find(|proj| proj.name == project || proj.get_path() == project);
+        let Some(found) = found else {
+            let err = format!("No project '{project}' on server");
+            return Err(io::Error::new(io::ErrorKind::NotFound, err));
+        };
+        let path = found.get_path();
+        let socket = requester.serve_project(path).await?;
+        let Some(local) = forward_at else {
+            return run_editor(cfg, &socket).await;
+        };
+        let listener = unix::listen(&local)?;
+        select! {
+            res = tunnel::forward(requester.clone(), path, listener) => res,
+            res = run_editor(cfg, &local) => res,
+        }
+    };
+    select! {
+        res = attached => res,
+        res = client.go_loop() => Err(res.err().unwrap_or(ProtocolError::Closed).into()),
+    }
+}
+
+/// Editor is told to connect to `socket` with `--server`, as neovide does.
+async fn run_editor(cfg: &Config, socket: &Path) -> io::Result<()> {
+    let editor = cfg.editor().value;
+    let mut command = Command::new(&editor.command);
+    let status = command
+        .args(&editor.args)
+        .arg("--server")
+        .arg(socket)
+        .status()
+        .await?;
+    if status.success() {
+        Ok(())
+    } else {
+        let err = format!("{} exited with {status}", editor.command);
+        Err(io::Error::other(err))
+    }
+}
@@ -0,0 +1,8 @@
+use ide::Config;
+use std::io;
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let cfg = Config::new();
+    ide::daemon::run(&cfg).await
+}
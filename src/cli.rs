@@ -0,0 +1,155 @@
+//! Command-line interface of `ide`, parsed by `clap`.
+
+use crate::{Config, Project};
+use clap::{CommandFactory as _, Parser, Subcommand};
+use clap_complete::Shell;
+use std::io::{self, Write};
+use std::time::UNIX_EPOCH;
+
+/// Opens projects, i.e. editor sessions, locally or served by other machines.
+#[derive(Parser)]
+#[command(version)]
+pub struct Cli {
+    /// Folder with sessions of editor.
+    #[arg(long, global = true, value_name = "DIR")]
+    sessions_dir: Option<String>,
+    /// Editor to open projects with, arguments are separated by whitespace.
+    #[arg(long, global = true, value_name = "COMMAND")]
+    editor: Option<String>,
+    /// Socket the server listens at and clients connect to.
+    #[arg(long, global = true, value_name = "PATH")]
+    server_socket: Option<String>,
+    /// File with token clients of server may authenticate with.
+    #[arg(long, global = true, value_name = "PATH")]
+    token_file: Option<String>,
+    /// Project to open in-place, same as `ide open`. UI is shown if missing.
+    project: Option<String>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Lists projects, the most recently opened first.
+    List {
+        /// Prints projects as JSON array.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Opens project by name or path.
+    Open { project: String },
+    /// Removes session of project by name or path.
+    Rm { project: String },
+    /// Serves projects over the idep protocol, same as `ide-server`.
+    Serve,
+    /// Opens editor attached to project served by server.
+    Attach {
+        /// Project by name or path on server.
+        project: String,
+        /// Remote server from config, the local one if not set.
+        #[arg(long)]
+        server: Option<String>,
+    },
+    /// Chooses project to open with UI.
+    Ui {
+        #[arg(long, value_parser = ["gtk", "stdio", "stdout"])]
+        ui: Option<String>,
+    },
+    /// Manages configuration.
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Prints completion script for shell.
+    Completions { shell: Shell },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Prints effective settings and where each of them came from.
+    Show,
+}
+
+impl Cli {
+    /// Overrides settings of `cfg` with the ones given on command line.
+    pub fn configure(&self, cfg: &mut Config) {
+        if let Some(path) = &self.sessions_dir {
+            cfg.set_folder(path);
+        }
+        if let Some(editor) = &self.editor {
+            cfg.set_editor(editor);
+        }
+        if let Some(path) = &self.server_socket {
+            cfg.set_server_socket(path);
+        }
+        if let Some(path) = &self.token_file {
+            cfg.set_token_file(path);
+        }
+    }
+
+    /// Runs command with `cfg`, printing its output to `out`. Commands
+    /// opening editor return only if they fail.
+    pub fn run(self, mut cfg: Config, out: &mut impl Write) -> io::Result<()> {
+        let Some(command) = self.command else {
+            let projects = cfg.get_projects()?;
+            return match self.project {
+                Some(name) => cfg.exec_from(projects, &name),
+                None => cfg.run_ui(projects),
+            };
+        };
+        match command {
+            Command::List { json } => list(&cfg, json, out),
+            Command::Open { project } => cfg.exec_from(cfg.get_projects()?, &project),
+            Command::Rm { project } => {
+                let project = cfg.remove_project(&project)?;
+                writeln!(out, "Removed session of {}", project.get_path())
+            }
+            Command::Serve => runtime()?.block_on(crate::daemon::run(&cfg)),
+            Command::Attach { project, server } => {
+                let attach = crate::attach::attach(&cfg, &project, server.as_deref());
+                runtime()?.block_on(attach)
+            }
+            Command::Ui { ui } => {
+                if let Some(ui) = ui {
+                    cfg.set_ui(&ui)?;
+                }
+                cfg.run_ui(cfg.get_projects()?)
+            }
+            Command::Config {
+                command: ConfigCommand::Show,
+            } => write!(out, "{}", cfg.show()),
+            Command::Completions { shell } => {
+                clap_complete::generate(shell, &mut Cli::command(), "ide", out);
+                Ok(())
+            }
+        }
+    }
+}
+
+fn runtime() -> io::Result<tokio::runtime::Runtime> {
+    tokio::runtime::Runtime::new()
+}
+
+fn list(cfg: &Config, json: bool, out: &mut impl Write) -> io::Result<()> {
+    let projects: Vec<Project> = cfg.get_projects()?.collect();
+    if !json {
+        let shift = Project::max_name_length(&projects);
+        for project in &projects {
+            writeln!(out, "{:<shift$} {}", project.name, project.get_path())?;
+        }
+        return Ok(());
+    }
+    let projects = projects.iter().map(|project| {
+        let last_modified = project.last_modified.map(|time| {
+            let since_epoch = time.duration_since(UNIX_EPOCH);
+            since_epoch.unwrap_or_default().as_secs()
+        });
+        serde_json::json!({
+            "name": project.name,
+            "path": project.get_path(),
+            "exists": project.exists,
+            "last_modified": last_modified,
+        })
+    });
+    writeln!(out, "{}", serde_json::Value::from_iter(projects))
+}
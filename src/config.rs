@@ -0,0 +1,204 @@
+use serde::Deserialize;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where the effective value of setting came from. Values of config file are
+/// overridden by environment, and those are overridden by command line.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Default,
+    /// Editor preferred by UI, see `ui::UiFactory::preferred_editor`.
+    Ui,
+    File(PathBuf),
+    Env(&'static str),
+    Cli,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::Ui => write!(f, "preferred by UI"),
+            Source::File(path) => write!(f, "file {}", path.to_str().unwrap()),
+            Source::Env(name) => write!(f, "env {name}"),
+            Source::Cli => write!(f, "command line"),
+        }
+    }
+}
+
+/// Value of setting along with its source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Setting<T> {
+    pub value: T,
+    pub source: Source,
+}
+
+impl<T> Setting<T> {
+    pub fn new(value: T, source: Source) -> Self {
+        Self { value, source }
+    }
+}
+
+/// Command to open projects with, launched at the project directory.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Editor {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+impl Editor {
+    /// Splits `line` by whitespace, e.g. `$IDE_EDITOR`.
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut words = line.split_whitespace().map(String::from);
+        Some(Self {
+            command: words.next()?,
+            args: words.collect(),
+        })
+    }
+}
+
+impl fmt::Display for Editor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.command)?;
+        for arg in &self.args {
+            write!(f, " {arg}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Server of other machine, reachable over TLS, see `Listen`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RemoteServer {
+    pub name: String,
+    /// `host:port` the server listens at.
+    pub address: String,
+    /// File with token to authenticate with, see `protocol::auth`.
+    pub token_file: PathBuf,
+    /// PEM file with certificate of server, or of authority which issued it.
+    pub ca_file: PathBuf,
+}
+
+impl RemoteServer {
+    /// Host part of `address`, which certificate of server should be issued
+    /// for.
+    pub fn host(&self) -> &str {
+        let host = match self.address.rsplit_once(':') {
+            Some((host, _port)) => host,
+            None => &self.address,
+        };
+        host.trim_start_matches('[').trim_end_matches(']')
+    }
+}
+
+/// Address server listens at for clients of other machines, in addition to
+/// its Unix socket. Connections are encrypted with TLS, and clients have to
+/// authenticate with token, see `File::token_file`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Listen {
+    /// `host:port` to listen at.
+    pub address: String,
+    /// PEM file with certificate chain of server.
+    pub cert_file: PathBuf,
+    /// PEM file with private key of server.
+    pub key_file: PathBuf,
+}
+
+/// Content of config file, all settings are optional:
+///
+/// ```toml
+/// sessions_folder = "~/.local/share/nvim/sessions"
+/// ui = "Gtk"
+/// server_socket = "/run/user/1000/idep.sock"
+/// token_file = "~/.config/ide/token"
+///
+/// [editor]
+/// command = "neovide"
+/// args = ["--no-fork"]
+///
+/// [listen]
+/// address = "0.0.0.0:7777"
+/// cert_file = "~/.config/ide/server.pem"
+/// key_file = "~/.config/ide/server.key"
+///
+/// [[servers]]
+/// name = "workstation"
+/// address = "workstation.local:7777"
+/// token_file = "~/.config/ide/workstation.token"
+/// ca_file = "~/.config/ide/workstation.pem"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct File {
+    pub sessions_folder: Option<PathBuf>,
+    pub editor: Option<Editor>,
+    pub ui: Option<String>,
+    pub server_socket: Option<PathBuf>,
+    /// File with token clients of server may use, see `Config::token_file`.
+    pub token_file: Option<PathBuf>,
+    pub listen: Option<Listen>,
+    #[serde(default)]
+    pub servers: Vec<RemoteServer>,
+}
+
+impl File {
+    /// Reads config from `path`. Missing file is the empty config. Paths
+    /// starting with `~/` are relative to home directory.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err),
+        };
+        let mut file: Self = toml::from_str(&content)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let paths = file
+            .sessions_folder
+            .iter_mut()
+            .chain(file.server_socket.iter_mut())
+            .chain(file.token_file.iter_mut());
+        let listen = file
+            .listen
+            .iter_mut()
+            .flat_map(|listen| [&mut listen.cert_file, &mut listen.key_file]);
+        let servers = file
+            .servers
+            .iter_mut()
+            .flat_map(|srv| [&mut srv.token_file, &mut srv.ca_file]);
+        for path in paths.chain(listen).chain(servers) {
+            *path = expand_home(path);
+        }
+        Ok(file)
+    }
+}
+
+/// Returns `$IDE_CONFIG` if set, or `$XDG_CONFIG_HOME/ide/config.toml`, or
+/// the same at `~/.config`.
+pub fn default_file() -> PathBuf {
+    if let Ok(path) = env::var("IDE_CONFIG") {
+        return PathBuf::from(path);
+    }
+    let config_home = match env::var("XDG_CONFIG_HOME") {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => home_dir().join(".config"),
+    };
+    config_home.join("ide/config.toml")
+}
+
+pub(crate) fn home_dir() -> PathBuf {
+    PathBuf::from(env::var("HOME").unwrap_or(String::from(".")))
+}
+
+fn expand_home(path: &Path) -> PathBuf {
+    match path.strip_prefix("~") {
+        Ok(rest) => home_dir().join(rest),
+        Err(_) => path.into(),
+    }
+}
@@ -0,0 +1,192 @@
+#[cfg(feature = "tls")]
+use crate::config::Listen;
+use crate::protocol::auth::{Peer, PeerUidAuthenticator, TokenAuthenticator};
+#[cfg(feature = "tls")]
+use crate::protocol::streams::tls;
+use crate::protocol::{streams::unix, Context, ProtocolError, Receiver, Sender};
+use crate::protocol::{Server, SharedContext};
+use crate::registry::Registry;
+use crate::watcher::Watcher;
+use crate::{project, Config};
+use std::future::Future;
+use std::io;
+#[cfg(feature = "tls")]
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+#[cfg(feature = "tls")]
+use tokio::time;
+use tokio::{pin, select, task};
+
+/// Time remote client has to finish TLS handshake in.
+#[cfg(feature = "tls")]
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Serves the projects of `cfg`, as well as the ones registered by previous
+/// runs, on its server socket until SIGTERM or SIGINT is received. Only
+/// processes of the same user, or clients which know the token of `cfg`, are
+/// served. Sessions written or removed while serving are tracked too.
+///
+/// Clients of other machines are served too when `cfg` has `listen` address,
+/// which requires token, see `listen_remote`.
+pub async fn run(cfg: &Config) -> io::Result<()> {
+    // Watched before listing, so no session is missed
+    let watcher = cfg.watch_projects()?;
+    let registry = Registry::load(cfg.registry_file())?;
+    let mut projects = registry.merge(cfg.get_projects()?);
+    project::sort_by_recency(&mut projects);
+    let mut context = Context::new(projects, cfg.headless().clone());
+    context.set_registry(registry);
+    context.add_authenticator(PeerUidAuthenticator::current_user());
+    if let Some(path) = cfg.token_file() {
+        context.add_authenticator(TokenAuthenticator::from_file(path)?);
+    }
+    let listener = unix::listen(cfg.server_socket())?;
+    println!("Serving at {}", listener.path().to_str().unwrap());
+    let context = context.into_shared();
+    let remote = spawn_remote(cfg, context.clone()).await?;
+    let sessions = tokio::spawn(watch_sessions(context.clone(), watcher));
+    let res = serve_until(listener, context, shutdown_signal()?).await;
+    sessions.abort();
+    if let Some(remote) = remote {
+        remote.abort();
+        let _ = remote.await;
+    }
+    res
+}
+
+#[cfg(feature = "tls")]
+async fn spawn_remote(
+    cfg: &Config,
+    context: SharedContext,
+) -> io::Result<Option<task::JoinHandle<()>>> {
+    let Some(listen) = cfg.listen() else {
+        return Ok(None);
+    };
+    if cfg.token_file().is_none() {
+        let err = format!("Listening at {} requires token_file", listen.address);
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, err));
+    }
+    let listener = listen_remote(listen).await?;
+    println!("Serving at {}", listener.local_addr()?);
+    Ok(Some(tokio::spawn(serve_remote(listener, context))))
+}
+
+#[cfg(not(feature = "tls"))]
+async fn spawn_remote(
+    cfg: &Config,
+    _context: SharedContext,
+) -> io::Result<Option<task::JoinHandle<()>>> {
+    match cfg.listen() {
+        Some(listen) => {
+            let err = format!("Listening at {} requires tls feature", listen.address);
+            Err(io::Error::new(io::ErrorKind::Unsupported, err))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Listens at address of `listen` with its TLS certificate. Clients of such
+/// listener have no Unix credentials, so they are served only if context has
+/// `TokenAuthenticator`.
+#[cfg(feature = "tls")]
+pub async fn listen_remote(listen: &Listen) -> io::Result<tls::Listener> {
+    let identity = tls::Identity::from_pem_files(&listen.cert_file, &listen.key_file)?;
+    let config = tls::server_config(identity, None)?;
+    tls::listen(&listen.address, config).await
+}
+
+/// Accepts clients at TLS `listener` and serves each of them in its own task,
+/// like `serve_until` does. The handshake is done in the task too, so clients
+/// which do not finish it in `HANDSHAKE_TIMEOUT` do not stop others. Clients
+/// are dropped when the returned future is.
+#[cfg(feature = "tls")]
+pub async fn serve_remote(listener: tls::Listener, context: SharedContext) {
+    let mut clients = task::JoinSet::new();
+    loop {
+        select! {
+            incoming = listener.accept() => match incoming {
+                Ok((handshake, addr)) => {
+                    clients.spawn(serve_remote_client(handshake, addr, context.clone()));
+                }
+                Err(err) => eprintln!("Failed to accept remote client: {err}"),
+            },
+            Some(_) = clients.join_next() => (),
+        }
+    }
+}
+
+#[cfg(feature = "tls")]
+async fn serve_remote_client(
+    handshake: tls::Handshake,
+    addr: std::net::SocketAddr,
+    context: SharedContext,
+) {
+    match time::timeout(HANDSHAKE_TIMEOUT, handshake.finish()).await {
+        Ok(Ok(stream)) => serve(Server::with_context(stream, context)).await,
+        Ok(Err(err)) => eprintln!("Failed TLS handshake with {addr}: {err}"),
+        Err(_) => eprintln!("No TLS handshake with {addr} in {HANDSHAKE_TIMEOUT:?}"),
+    }
+}
+
+async fn watch_sessions(context: SharedContext, watcher: Watcher) {
+    if let Err(err) = Context::watch_sessions(context, watcher).await {
+        eprintln!("Stopped watching sessions: {err}");
+    }
+}
+
+/// Accepts clients at `listener` and serves each of them in its own task, so
+/// clients are served in parallel on multi-threaded runtime. Serving stops
+/// when `shutdown` completes: the socket file is removed, as well as all
+/// editors launched by clients are terminated.
+pub async fn serve_until<F>(
+    listener: unix::Listener,
+    context: SharedContext,
+    shutdown: F,
+) -> io::Result<()>
+where
+    F: Future<Output = ()>,
+{
+    pin!(shutdown);
+    let supervisor = tokio::spawn(Context::supervise(context.clone()));
+    let mut clients = task::JoinSet::new();
+    loop {
+        select! {
+            stream = listener.accept() => match stream {
+                Ok(stream) => serve_client(&mut clients, stream, context.clone()),
+                Err(err) => eprintln!("Failed to accept client: {err}"),
+            },
+            // Outputs of finished clients are kept until joined
+            Some(_) = clients.join_next() => (),
+            _ = &mut shutdown => break,
+        }
+    }
+    clients.shutdown().await;
+    supervisor.abort();
+    let _ = supervisor.await;
+    Ok(())
+}
+
+fn serve_client(clients: &mut task::JoinSet<()>, stream: unix::UnixStream, context: SharedContext) {
+    let uid = unix::peer_uid(&stream).ok();
+    let mut server = Server::with_context(stream, context);
+    server.set_peer(Peer { uid });
+    clients.spawn(serve(server));
+}
+
+async fn serve<S: Sender, R: Receiver>(mut server: Server<S, R>) {
+    match server.next().await {
+        Ok(()) | Err(ProtocolError::Closed) => (),
+        Err(err) => eprintln!("Client disconnected: {err}"),
+    }
+}
+
+fn shutdown_signal() -> io::Result<impl Future<Output = ()>> {
+    let mut term = signal(SignalKind::terminate())?;
+    let mut int = signal(SignalKind::interrupt())?;
+    Ok(async move {
+        select! {
+            _ = term.recv() => (),
+            _ = int.recv() => (),
+        }
+    })
+}
@@ -1,77 +1,304 @@
 #![feature(trait_alias)]
 
 use std::env;
+use std::fmt::Write as _;
 use std::fs::read_dir;
 use std::io;
 use std::os::unix::process::CommandExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
+pub mod attach;
+pub mod cli;
+pub mod config;
+pub mod daemon;
 pub mod project;
+pub mod registry;
 pub mod ui;
 pub mod protocol;
+pub mod watcher;
 
+use config::{Editor, Listen, RemoteServer, Setting, Source};
 pub use project::Project;
+use protocol::headless::Headless;
+use registry::Registry;
+use watcher::Watcher;
 
 type Projects = Box<dyn Iterator<Item = Project>>;
 
+/// UI used unless other one is configured, see `ui::new`.
+const DEFAULT_UI: &str = "Gtk";
+
 pub struct Config {
-    sessions_folder: PathBuf,
-    editor: String,
+    file: PathBuf,
+    sessions_folder: Setting<PathBuf>,
+    /// Falls back to the editor preferred by UI when not set, see `editor`.
+    editor: Option<Setting<Editor>>,
+    ui_name: Setting<String>,
+    server_socket: Setting<PathBuf>,
+    token_file: Option<Setting<PathBuf>>,
+    listen: Option<Listen>,
+    servers: Vec<RemoteServer>,
+    registry_file: PathBuf,
+    history_file: PathBuf,
+    headless: Headless,
     ui: Box<dyn ui::UiFactory>,
 }
 
+fn default_server_socket() -> PathBuf {
+    match env::var("XDG_RUNTIME_DIR") {
+        Ok(dir) => PathBuf::from(dir).join("idep.sock"),
+        Err(_) => {
+            let user = env::var("USER").unwrap_or(String::from("ide"));
+            env::temp_dir().join(format!("idep-{user}.sock"))
+        }
+    }
+}
+
 impl Config {
+    /// Reads config file, see `config::default_file`, and environment.
+    /// Broken config file is reported and ignored.
     pub fn new() -> Config {
-        let home = env::var("HOME").unwrap_or(String::from("."));
-        let mut path = PathBuf::from(home);
-        let mut editor = env::var("EDITOR").unwrap_or(String::from("vim"));
-        let ui_name = env::var("UI");
-        let ui = match ui_name {
-            Ok(ui_name) => ui::from(&ui_name).expect(&format!("No '{ui_name}' UI available")),
-            Err(_) => ui::new(),
+        let path = config::default_file();
+        let file = config::File::load(&path).unwrap_or_else(|err| {
+            eprintln!("Failed to read config '{}': {err}", path.to_str().unwrap());
+            Default::default()
+        });
+        Self::with_file(file, &path)
+    }
+
+    /// Takes settings of config `file` read from `path`, unless they are
+    /// overridden by environment. Unknown UI is reported and the default one
+    /// is used instead.
+    pub fn with_file(file: config::File, path: &Path) -> Config {
+        let sessions_folder = config::home_dir().join(".local/share/nvim/sessions");
+        let mut cfg = Config {
+            file: path.into(),
+            sessions_folder: Setting::new(sessions_folder, Source::Default),
+            editor: None,
+            ui_name: Setting::new(String::from(DEFAULT_UI), Source::Default),
+            server_socket: Setting::new(default_server_socket(), Source::Default),
+            token_file: None,
+            listen: file.listen,
+            servers: file.servers,
+            registry_file: registry::default_file(),
+            history_file: registry::default_history_file(),
+            headless: Headless::default(),
+            ui: ui::new(),
         };
 
-        if let Some(e) = ui.preferred_editor() {
-            editor = e;
-        }
+        let source = Source::File(path.into());
+        set_from(&mut cfg.sessions_folder, file.sessions_folder, &source);
+        cfg.editor = file
+            .editor
+            .map(|editor| Setting::new(editor, source.clone()));
+        set_from(&mut cfg.ui_name, file.ui, &source);
+        set_from(&mut cfg.server_socket, file.server_socket, &source);
+        cfg.token_file = file
+            .token_file
+            .map(|path| Setting::new(path, source.clone()));
 
-        path.push(".local/share/nvim/sessions");
+        let var = |name| env::var(name).ok();
+        let path_var = |name| var(name).map(PathBuf::from);
+        let source = Source::Env("IDE_SESSIONS_DIR");
+        set_from(
+            &mut cfg.sessions_folder,
+            path_var("IDE_SESSIONS_DIR"),
+            &source,
+        );
+        if let Some(editor) = var("IDE_EDITOR").as_deref().and_then(Editor::parse) {
+            cfg.editor = Some(Setting::new(editor, Source::Env("IDE_EDITOR")));
+        }
+        set_from(&mut cfg.ui_name, var("UI"), &Source::Env("UI"));
+        let source = Source::Env("IDE_SOCKET");
+        set_from(&mut cfg.server_socket, path_var("IDE_SOCKET"), &source);
+        if let Some(path) = path_var("IDE_TOKEN_FILE") {
+            cfg.token_file = Some(Setting::new(path, Source::Env("IDE_TOKEN_FILE")));
+        }
 
-        Config {
-            sessions_folder: path,
-            editor,
-            ui,
+        match ui::from(&cfg.ui_name.value) {
+            Some(ui) => cfg.ui = ui,
+            None => {
+                let ui_name = &cfg.ui_name;
+                eprintln!(
+                    "No '{}' UI available, set by {}, using {DEFAULT_UI}",
+                    ui_name.value, ui_name.source
+                );
+                cfg.ui_name = Setting::new(String::from(DEFAULT_UI), Source::Default);
+            }
         }
+        cfg
     }
 
     pub fn set_folder(&mut self, path: &str) {
-        self.sessions_folder = PathBuf::from(path);
+        self.sessions_folder = Setting::new(PathBuf::from(path), Source::Cli);
+    }
+
+    /// Sets editor command with arguments separated by whitespace. Empty
+    /// `line` resets editor to the one preferred by UI.
+    pub fn set_editor(&mut self, line: &str) {
+        self.editor = Editor::parse(line).map(|editor| Setting::new(editor, Source::Cli));
+    }
+
+    /// Returns editor to open projects with. Unless set explicitly, it is the
+    /// one preferred by UI, or `$EDITOR`, or vim.
+    pub fn editor(&self) -> Setting<Editor> {
+        if let Some(editor) = &self.editor {
+            return editor.clone();
+        }
+        let preferred = self.ui.preferred_editor();
+        if let Some(editor) = preferred.as_deref().and_then(Editor::parse) {
+            return Setting::new(editor, Source::Ui);
+        }
+        match env::var("EDITOR").ok().as_deref().and_then(Editor::parse) {
+            Some(editor) => Setting::new(editor, Source::Env("EDITOR")),
+            None => Setting::new(Editor::parse("vim").unwrap(), Source::Default),
+        }
+    }
+
+    pub fn set_ui(&mut self, name: &str) -> io::Result<()> {
+        let Some(ui) = ui::from(name) else {
+            let err = format!("No '{name}' UI available");
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, err));
+        };
+        self.ui = ui;
+        self.ui_name = Setting::new(name.into(), Source::Cli);
+        Ok(())
+    }
+
+    pub fn set_server_socket(&mut self, path: &str) {
+        self.server_socket = Setting::new(PathBuf::from(path), Source::Cli);
+    }
+
+    pub fn server_socket(&self) -> &Path {
+        &self.server_socket.value
+    }
+
+    /// Sets file with token which clients may use to authenticate, see
+    /// `protocol::auth::read_token`.
+    pub fn set_token_file(&mut self, path: &str) {
+        self.token_file = Some(Setting::new(PathBuf::from(path), Source::Cli));
+    }
+
+    pub fn token_file(&self) -> Option<&Path> {
+        self.token_file
+            .as_ref()
+            .map(|token_file| token_file.value.as_path())
+    }
+
+    /// Address to serve clients of other machines at, see `config::Listen`.
+    pub fn listen(&self) -> Option<&Listen> {
+        self.listen.as_ref()
+    }
+
+    /// Servers of other machines listed in config file.
+    pub fn servers(&self) -> &[RemoteServer] {
+        &self.servers
+    }
+
+    /// Describes effective settings in the format of config file, with the
+    /// source of each setting.
+    pub fn show(&self) -> String {
+        let mut out = format!("# {}\n", self.file.to_str().unwrap());
+        let mut line = |name: &str, value: &dyn std::fmt::Debug, source: &Source| {
+            let _ = writeln!(out, "{name} = {value:?}  # {source}");
+        };
+        let folder = &self.sessions_folder;
+        line("sessions_folder", &folder.value, &folder.source);
+        let editor = self.editor();
+        line("editor", &editor.value.to_string(), &editor.source);
+        line("ui", &self.ui_name.value, &self.ui_name.source);
+        line(
+            "server_socket",
+            &self.server_socket.value,
+            &self.server_socket.source,
+        );
+        if let Some(token_file) = &self.token_file {
+            line("token_file", &token_file.value, &token_file.source);
+        }
+        let source = Source::File(self.file.clone());
+        if let Some(listen) = &self.listen {
+            let _ = writeln!(out, "\n[listen]  # {source}");
+            let _ = writeln!(out, "address = {:?}", listen.address);
+            let _ = writeln!(out, "cert_file = {:?}", listen.cert_file);
+            let _ = writeln!(out, "key_file = {:?}", listen.key_file);
+        }
+        for server in &self.servers {
+            let _ = writeln!(out, "\n[[servers]]  # {source}");
+            let _ = writeln!(out, "name = {:?}", server.name);
+            let _ = writeln!(out, "address = {:?}", server.address);
+            let _ = writeln!(out, "token_file = {:?}", server.token_file);
+            let _ = writeln!(out, "ca_file = {:?}", server.ca_file);
+        }
+        out
+    }
+
+    /// Sets file where server keeps its projects, see `registry::Registry`.
+    pub fn set_registry_file(&mut self, path: &str) {
+        self.registry_file = PathBuf::from(path);
+    }
+
+    pub fn registry_file(&self) -> &Path {
+        &self.registry_file
+    }
+
+    /// Sets file where projects opened by `exec` are remembered, so the
+    /// projects could be ordered by the time of opening.
+    pub fn set_history_file(&mut self, path: &str) {
+        self.history_file = PathBuf::from(path);
+    }
+
+    pub fn set_headless(&mut self, headless: Headless) {
+        self.headless = headless;
+    }
+
+    pub fn headless(&self) -> &Headless {
+        &self.headless
     }
 
     pub fn create_project(&self, name: &str) -> Project {
-        let mut concrete = self.sessions_folder.clone();
+        let mut concrete = self.sessions_folder.value.clone();
         concrete.push(name);
         Project::from_session_file(concrete)
     }
 
+    /// Returns projects of the sessions folder, the most recently opened or
+    /// modified first.
     pub fn get_projects(&self) -> io::Result<Projects> {
-        let it = read_dir(&self.sessions_folder)?;
+        let it = read_dir(&self.sessions_folder.value)?;
         let it = it.filter_map(|path| -> Option<Project> {
             if let Ok(path) = path {
                 return Some(Project::from_session_file(path.path()));
             }
             None
         });
+        let mut projects: Vec<Project> = it.collect();
 
-        Ok(Box::new(it))
+        let history = Registry::load(&self.history_file).unwrap_or_else(|err| {
+            eprintln!("Failed to read history of projects: {err}");
+            Registry::new(&self.history_file)
+        });
+        for project in projects.iter_mut() {
+            history.stamp(project);
+        }
+        project::sort_by_recency(&mut projects);
+
+        Ok(Box::new(projects.into_iter()))
+    }
+
+    /// Returns watcher of sessions appearing in and vanishing from the
+    /// sessions folder.
+    pub fn watch_projects(&self) -> io::Result<Watcher> {
+        Watcher::new(&self.sessions_folder.value)
     }
 
     pub fn run_ui(&self, mut projects: Projects) -> io::Result<()> {
-        self.ui
-            .new(projects.as_mut())
-            .run()
-            .map_or(Ok(()), |proj| Err(self.exec(&proj)))
+        let mut ui = self.ui.new(projects.as_mut());
+        match self.watch_projects() {
+            Ok(watcher) => ui.watch(watcher),
+            Err(err) => eprintln!("Failed to watch sessions: {err}"),
+        }
+        ui.run().map_or(Ok(()), |proj| Err(self.exec(&proj)))
     }
 
     pub fn exec_from(&self, mut projects: Projects, proj_name: &str) -> io::Result<()> {
@@ -84,13 +311,40 @@ impl Config {
         Err(self.exec(&proj))
     }
 
+    /// Removes session of project given by name or path.
+    pub fn remove_project(&self, name: &str) -> io::Result<Project> {
+        let mut projects = self.get_projects()?;
+        let Some(project) = projects.find(|proj| proj.name == name || proj.get_path() == name)
+        else {
+            let err = format!("No project '{name}'");
+            return Err(io::Error::new(io::ErrorKind::NotFound, err));
+        };
+        project.rm()?;
+        Ok(project)
+    }
+
     pub fn exec(&self, project: &Project) -> io::Error {
-        println!(
-            "Running {} at {}",
-            self.editor,
-            project.path.to_str().unwrap()
-        );
-        Command::new(&self.editor).current_dir(&project.path).exec()
+        if let Err(err) = self.remember_opening(project) {
+            eprintln!("Failed to remember opening of project: {err}");
+        }
+        let editor = self.editor().value;
+        println!("Running {} at {}", editor, project.path.to_str().unwrap());
+        Command::new(&editor.command)
+            .args(&editor.args)
+            .current_dir(&project.path)
+            .exec()
+    }
+
+    fn remember_opening(&self, project: &Project) -> io::Result<()> {
+        let mut history = Registry::load(&self.history_file)?;
+        history.mark_opened(project);
+        history.save()
+    }
+}
+
+fn set_from<T>(setting: &mut Setting<T>, value: Option<T>, source: &Source) {
+    if let Some(value) = value {
+        *setting = Setting::new(value, source.clone());
     }
 }
 
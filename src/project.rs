@@ -1,7 +1,9 @@
 use paste;
+use std::cmp::Reverse;
 use std::path::PathBuf;
 use std::fs;
 use std::io;
+use std::time::SystemTime;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Project {
@@ -9,6 +11,9 @@ pub struct Project {
     pub path: PathBuf,
     pub session_file: Option<PathBuf>,
     pub exists: bool,
+    pub socket: Option<PathBuf>,
+    /// When the project was opened or its session saved the last time.
+    pub last_modified: Option<SystemTime>,
 }
 
 fn widthdraw_path_from_session_name(path: &str) -> PathBuf {
@@ -76,6 +81,9 @@ impl Project {
                 path
             },
         };
+        let last_modified = session_file
+            .as_ref()
+            .and_then(|file| fs::metadata(file).and_then(|meta| meta.modified()).ok());
         Project {
             name: String::from(
                 path.file_name()
@@ -85,7 +93,9 @@ impl Project {
             ),
             path,
             session_file,
-            exists
+            exists,
+            socket: None,
+            last_modified,
         }
     }
 
@@ -101,14 +111,38 @@ impl Project {
         &self.path.to_str().unwrap_or("")
     }
 
+    /// Name of the project in terms of neovim-session-manager: the path with
+    /// all separators replaced by `__`.
+    pub fn get_session_name(&self) -> String {
+        self.get_path().replace("/", "__")
+    }
+
+    pub fn is_serving(&self) -> bool {
+        self.socket.is_some()
+    }
+
+    /// Takes opening of the project at `time` into account, unless it is
+    /// known to be modified later.
+    pub fn opened_at(&mut self, time: SystemTime) {
+        self.last_modified = self.last_modified.max(Some(time));
+    }
+
     max_length!(name);
     max_length!(path);
 }
 
+/// Orders `projects` most recent first. Projects of unknown age go last.
+pub fn sort_by_recency(projects: &mut [Project]) {
+    projects.sort_by_key(|proj| Reverse(proj.last_modified));
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{widthdraw_path_from_session, widthdraw_path_from_session_name};
+    use super::{
+        sort_by_recency, widthdraw_path_from_session, widthdraw_path_from_session_name, Project,
+    };
     use std::path::PathBuf;
+    use std::time::{Duration, UNIX_EPOCH};
 
     #[test]
     fn check_widthdraw_path_from_session_name() {
@@ -125,4 +159,24 @@ mod tests {
             PathBuf::from("/tmp/test1/test2")
         )
     }
+
+    #[test]
+    fn check_session_name() {
+        let prj = Project::from_path("/tmp/test1/test2");
+        assert_eq!(prj.get_session_name(), "__tmp__test1__test2");
+    }
+
+    #[test]
+    fn check_sort_by_recency() {
+        let mut old = Project::from_path("/tmp/old");
+        old.opened_at(UNIX_EPOCH + Duration::from_secs(1));
+        let mut new = Project::from_path("/tmp/new");
+        new.opened_at(UNIX_EPOCH + Duration::from_secs(2));
+        old.opened_at(UNIX_EPOCH);
+        let unknown = Project::from_path("/tmp/unknown");
+        let mut projects = vec![unknown, old, new];
+        sort_by_recency(&mut projects);
+        let names: Vec<&str> = projects.iter().map(|proj| proj.name.as_str()).collect();
+        assert_eq!(names, ["new", "old", "unknown"]);
+    }
 }
@@ -0,0 +1,93 @@
+use super::idep;
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+/// What server knows about connected client apart from its `Hello`.
+#[derive(Debug, Clone, Default)]
+pub struct Peer {
+    /// Owner of process on the other side of Unix socket.
+    pub uid: Option<u32>,
+}
+
+/// Decides whether client may talk to server. Clients are authenticated by
+/// their `Hello`, all requests sent before it are rejected.
+pub trait Authenticator: Send + Sync {
+    fn authenticate(&self, hello: &idep::Hello, peer: &Peer) -> bool;
+}
+
+/// Accepts clients which send the same token as server has.
+pub struct TokenAuthenticator {
+    token: String,
+}
+
+impl TokenAuthenticator {
+    pub fn new(token: &str) -> Self {
+        Self {
+            token: token.into(),
+        }
+    }
+
+    /// Reads token with `read_token`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::new(&read_token(path)?))
+    }
+}
+
+impl Authenticator for TokenAuthenticator {
+    fn authenticate(&self, hello: &idep::Hello, _peer: &Peer) -> bool {
+        // Compare all bytes, so time does not tell how much of token matched
+        let (ours, theirs) = (self.token.as_bytes(), hello.token.as_bytes());
+        let pairs = ours.iter().zip(theirs);
+        let diff = pairs.fold(0, |diff, (a, b)| diff | (a ^ b));
+        ours.len() == theirs.len() && diff == 0
+    }
+}
+
+/// Accepts clients connected via Unix socket by processes of `uid` user.
+pub struct PeerUidAuthenticator {
+    uid: u32,
+}
+
+impl PeerUidAuthenticator {
+    pub fn new(uid: u32) -> Self {
+        Self { uid }
+    }
+
+    /// Accepts the user which runs server.
+    pub fn current_user() -> Self {
+        Self::new(unsafe { libc::getuid() })
+    }
+}
+
+impl Authenticator for PeerUidAuthenticator {
+    fn authenticate(&self, _hello: &idep::Hello, peer: &Peer) -> bool {
+        peer.uid == Some(self.uid)
+    }
+}
+
+/// Reads token from the first line of file at `path`. The file must not be
+/// accessible by other users, i.e. should have 0600 permissions.
+pub fn read_token<P: AsRef<Path>>(path: P) -> io::Result<String> {
+    let path = path.as_ref();
+    let mode = fs::metadata(path)?.permissions().mode();
+    if mode & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "Token file '{}' is accessible by others, its mode should be 0600",
+                path.to_str().unwrap()
+            ),
+        ));
+    }
+    let content = fs::read_to_string(path)?;
+    let token = content.lines().next().unwrap_or_default().trim();
+    if token.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Token file '{}' is empty", path.to_str().unwrap()),
+        ));
+    }
+    Ok(token.into())
+}
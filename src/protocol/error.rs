@@ -0,0 +1,77 @@
+use super::FrameVersion;
+use std::fmt;
+use std::io;
+
+/// Error which stops processing of protocol stream.
+#[derive(Debug)]
+pub enum ProtocolError {
+    /// Peer closed the connection.
+    Closed,
+    /// Underlying stream failed.
+    Io(io::Error),
+    /// Peer sent message which could not be parsed.
+    Parse(protobuf::Error),
+    /// Peer sent frame header of unknown version.
+    UnsupportedVersion(u8),
+    /// Peer sent frame of unknown type. Its payload is skipped, so the stream
+    /// remains consistent.
+    UnknownFrame {
+        typ: u8,
+        seq_id: u32,
+        len: u16,
+        version: FrameVersion,
+    },
+    /// Peer sent response to request which was not sent.
+    UnexpectedResponse(u32),
+    /// Peer sent message longer than `MAX_MESSAGE_LEN`.
+    TooLarge,
+    /// Handler of update failed.
+    Handler(io::Error),
+    /// Peer did not answer pings, see `Heartbeat`.
+    NotResponding,
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Closed => write!(f, "Connection closed"),
+            Self::Io(err) => write!(f, "I/O error: {err}"),
+            Self::Parse(err) => write!(f, "Malformed message: {err}"),
+            Self::UnsupportedVersion(version) => write!(f, "Unsupported frame version {version}"),
+            Self::UnknownFrame { typ, .. } => write!(f, "Invalid value of frame type {typ}"),
+            Self::UnexpectedResponse(seq_id) => write!(f, "Wrong seq_id {seq_id} in response"),
+            Self::TooLarge => write!(f, "Message is too large"),
+            Self::Handler(err) => write!(f, "Handler failed: {err}"),
+            Self::NotResponding => write!(f, "Peer is not responding"),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+impl From<io::Error> for ProtocolError {
+    fn from(value: io::Error) -> Self {
+        match value.kind() {
+            io::ErrorKind::UnexpectedEof => Self::Closed,
+            _ => Self::Io(value),
+        }
+    }
+}
+
+impl From<protobuf::Error> for ProtocolError {
+    fn from(value: protobuf::Error) -> Self {
+        Self::Parse(value)
+    }
+}
+
+impl From<ProtocolError> for io::Error {
+    fn from(value: ProtocolError) -> Self {
+        let kind = match &value {
+            ProtocolError::Closed => io::ErrorKind::ConnectionAborted,
+            ProtocolError::Io(err) | ProtocolError::Handler(err) => err.kind(),
+            ProtocolError::NotResponding => io::ErrorKind::TimedOut,
+            _ => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, value.to_string())
+    }
+}
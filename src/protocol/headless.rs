@@ -0,0 +1,135 @@
+use crate::Project;
+use std::env;
+use std::fs;
+use std::hash::{DefaultHasher, Hash as _, Hasher as _};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::{Child, Command};
+use tokio::sync::{mpsc, oneshot};
+use tokio::{select, time};
+
+/// Time given to editor to exit after `SIGTERM` before it is killed.
+const STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Length of project name kept in socket name. Paths of Unix sockets are
+/// limited to 108 bytes, so names are not allowed to grow with projects.
+const SOCKET_NAME_LEN: usize = 24;
+
+/// Command which launches editor instance serving project without UI.
+///
+/// The instance is started as `<program> <args>... --listen <socket>` inside
+/// the project folder. The session file of project, if any, is appended as
+/// `-S <session_file>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Headless {
+    pub program: String,
+    pub args: Vec<String>,
+    pub sockets_folder: PathBuf,
+    /// Time given to editor to create its socket, it is stopped otherwise.
+    pub start_timeout: Duration,
+}
+
+impl Default for Headless {
+    fn default() -> Self {
+        let sockets_folder = match env::var("XDG_RUNTIME_DIR") {
+            Ok(dir) => PathBuf::from(dir).join("ide"),
+            Err(_) => {
+                let user = env::var("USER").unwrap_or(String::from("ide"));
+                env::temp_dir().join(format!("ide-{user}"))
+            }
+        };
+        Self {
+            program: String::from("nvim"),
+            args: vec![String::from("--headless")],
+            sockets_folder,
+            start_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl Headless {
+    /// Socket is named after project, the hash of session name keeps names
+    /// of projects with the same name or long paths distinct.
+    pub fn socket_for(&self, project: &Project) -> PathBuf {
+        let allowed = |c: &char| c.is_ascii_alphanumeric() || "-_.".contains(*c);
+        let name: String = project
+            .name
+            .chars()
+            .filter(allowed)
+            .take(SOCKET_NAME_LEN)
+            .collect();
+        let mut hasher = DefaultHasher::new();
+        project.get_session_name().hash(&mut hasher);
+        self.sockets_folder
+            .join(format!("{name}-{:016x}.sock", hasher.finish()))
+    }
+
+    /// Launches editor for `project` listening at `socket`. The path of
+    /// project is sent to `exits` when the editor exits by any reason.
+    pub fn launch(
+        &self,
+        project: &Project,
+        socket: &Path,
+        exits: &mpsc::UnboundedSender<PathBuf>,
+    ) -> io::Result<Editor> {
+        let child = self.spawn(project, socket)?;
+        let (stop, stopped) = oneshot::channel();
+        let (path, exits) = (project.path.clone(), exits.clone());
+        tokio::spawn(async move {
+            supervise(child, stopped).await;
+            let _ = exits.send(path);
+        });
+        Ok(Editor { stop })
+    }
+
+    fn spawn(&self, project: &Project, socket: &Path) -> io::Result<Child> {
+        fs::create_dir_all(&self.sockets_folder)?;
+        let mut cmd = Command::new(&self.program);
+        cmd.args(&self.args).arg("--listen").arg(socket);
+        if let Some(session_file) = &project.session_file {
+            if session_file.exists() {
+                cmd.arg("-S").arg(session_file);
+            }
+        }
+        cmd.current_dir(&project.path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+    }
+}
+
+/// Handle of running editor. The editor is terminated when handle is stopped
+/// or dropped.
+pub struct Editor {
+    stop: oneshot::Sender<()>,
+}
+
+impl Editor {
+    pub fn stop(self) {
+        let _ = self.stop.send(());
+    }
+
+    /// Editor is not running anymore when its supervisor is done, even if
+    /// the exit is not handled yet.
+    pub fn is_running(&self) -> bool {
+        !self.stop.is_closed()
+    }
+}
+
+async fn supervise(mut child: Child, stopped: oneshot::Receiver<()>) {
+    select! {
+        _ = child.wait() => return,
+        _ = stopped => (),
+    }
+    if let Some(pid) = child.id() {
+        // SAFETY: the child is not reaped yet, so the pid is still owned by it
+        unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) };
+    }
+    if time::timeout(STOP_TIMEOUT, child.wait()).await.is_err() {
+        let _ = child.kill().await;
+    }
+}
@@ -1,64 +1,233 @@
 include!(concat!(env!("OUT_DIR"), "/protos/mod.rs"));
 
+pub mod auth;
+mod error;
+pub mod headless;
+pub mod reconnect;
 pub mod streams;
+pub mod tunnel;
 
+use crate::registry::Registry;
+use crate::watcher::{Event, Watcher};
 use crate::Project;
+use auth::{Authenticator, Peer};
 use byteorder::{ByteOrder as _, NetworkEndian as NE};
-use idep::{Response, Request};
+pub use error::ProtocolError;
+use futures::channel::mpsc as fmpsc;
+use headless::{Editor, Headless};
+pub use idep::hello::Capability;
+use idep::{response::Status, Request, Response};
 use protobuf::Message as _;
-use std::io;
-use streams::{BidirectSender, BidirectStream};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{fs, io};
+use streams::{BidirectSender, BidirectStream, Channel, Heartbeat};
+use tokio::io::unix::AsyncFd;
+use tokio::sync::{broadcast, mpsc};
+use tokio::{select, time};
 
 pub type Message = Vec<u8>;
 type Projects = Vec<Project>;
+pub type SharedContext = Arc<Mutex<Context>>;
 
-#[derive(PartialEq, Debug)]
+/// Stream of updates received by client, see `Client::subscribe`.
+pub type Updates = fmpsc::UnboundedReceiver<idep::OnUpdate>;
+type Subscribers = Vec<fmpsc::UnboundedSender<idep::OnUpdate>>;
+
+/// Update with the id of server which caused it.
+type Notification = (usize, idep::OnUpdate);
+/// Response to request and update to notify other clients about.
+type Handled = (Response, Option<idep::OnUpdate>);
+
+/// Time to wait for response of server, see `Client::set_request_timeout`.
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often socket of starting editor is checked, see
+/// `Headless::start_timeout`.
+const EDITOR_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Revision of `idep.proto`. Peers of different revisions refuse to talk,
+/// compatible changes are announced with `Capability` instead.
+pub const PROTOCOL_VERSION: u32 = 1;
+const SERVER_NAME: &str = concat!("ide-server ", env!("CARGO_PKG_VERSION"));
+const SERVER_CAPABILITIES: [Capability; 9] = [
+    Capability::LIST_PROJECTS,
+    Capability::NEW_PROJECT,
+    Capability::DELETE_PROJECT,
+    Capability::SERVE_PROJECT,
+    Capability::STOP_PROJECT,
+    Capability::UPDATES,
+    Capability::TUNNEL,
+    Capability::HEARTBEAT,
+    Capability::VERSIONED_FRAMES,
+];
+const CLIENT_CAPABILITIES: [Capability; 3] = [
+    Capability::UPDATES,
+    Capability::HEARTBEAT,
+    Capability::VERSIONED_FRAMES,
+];
+/// Error peers answer unknown requests with, legacy ones included.
+const UNKNOWN_REQUEST: &str = "Not implemented yet";
+
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum FrameType {
     Request = 1,
     Response = 2,
     Notify = 0,
+    /// Data of channel, which id is passed as `seq_id`, see `Channel`.
+    Channel = 3,
+    /// Liveness check, answered with `Pong` of the same `seq_id`. Both have
+    /// no payload, see `Heartbeat`.
+    Ping = 4,
+    Pong = 5,
+}
+
+/// Version of frame header.
+///
+/// `V1` is the legacy 4 bytes header: `type:u8 seq_id:u8 len:u16`.
+/// `V2` is 8 bytes header: `mark|version:u8 type:u8 seq_id:u32 len:u16`.
+/// Frame types never have the high bit set, so the `VERSION_MARK` in first
+/// byte distinguishes versioned headers from legacy ones.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
+pub enum FrameVersion {
+    V1 = 1,
+    V2 = 2,
+}
+
+const VERSION_MARK: u8 = 0x80;
+
+/// Flag of frame type which marks that the message is continued by the next
+/// frame. Messages longer than `MAX_FRAME_LEN` are split into such frames.
+const MORE_FLAG: u8 = 0x40;
+
+/// Maximum length of message carried by single frame.
+pub const MAX_FRAME_LEN: usize = u16::MAX as usize;
+
+/// Maximum length of message, including continued ones.
+pub const MAX_MESSAGE_LEN: usize = 16 * 1024 * 1024;
+
+impl FrameVersion {
+    /// Detects version of header which starts with `first` byte.
+    pub fn detect(first: u8) -> Result<Self, ProtocolError> {
+        if first & VERSION_MARK == 0 {
+            return Ok(Self::V1);
+        }
+        match first & !VERSION_MARK {
+            2 => Ok(Self::V2),
+            version => Err(ProtocolError::UnsupportedVersion(version)),
+        }
+    }
+
+    pub fn header_len(&self) -> usize {
+        match self {
+            Self::V1 => 4,
+            Self::V2 => 8,
+        }
+    }
+
+    pub fn max_seq_id(&self) -> u32 {
+        match self {
+            Self::V1 => u8::MAX.into(),
+            Self::V2 => u32::MAX,
+        }
+    }
 }
 
 pub struct Frame {
     pub typ: FrameType,
-    pub seq_id: u8,
+    pub seq_id: u32,
     pub len: u16,
+    pub version: FrameVersion,
+    pub more: bool,
 }
 
 impl Frame {
-    pub fn new(typ: FrameType, seq_id: u8, len: u16) -> Self {
-        Self { typ, seq_id, len }
+    pub fn new(typ: FrameType, seq_id: u32, len: u16) -> Self {
+        Self::with_version(FrameVersion::V2, typ, seq_id, len)
     }
-    pub fn len() -> usize {
-        4
+
+    pub fn with_version(version: FrameVersion, typ: FrameType, seq_id: u32, len: u16) -> Self {
+        debug_assert!(seq_id <= version.max_seq_id());
+        Self {
+            typ,
+            seq_id,
+            len,
+            version,
+            more: false,
+        }
     }
-}
 
-impl<C> From<&C> for Frame
-where
-    C: std::ops::Index<usize, Output = u8>,
-{
-    fn from(value: &C) -> Self {
-        let len = vec![value[2], value[3]];
-        Self::new(value[0].into(), value[1], NE::read_u16(&len))
+    pub fn header_len(&self) -> usize {
+        self.version.header_len()
+    }
+
+    /// Parses header at the start of `value`, which should contain at least
+    /// `FrameVersion::header_len` bytes.
+    pub fn parse<C>(value: &C) -> Result<Self, ProtocolError>
+    where
+        C: std::ops::Index<usize, Output = u8>,
+    {
+        let version = FrameVersion::detect(value[0])?;
+        let header: Message = (0..version.header_len()).map(|i| value[i]).collect();
+        let (typ, seq_id, len) = match version {
+            FrameVersion::V1 => (header[0], header[1].into(), NE::read_u16(&header[2..4])),
+            FrameVersion::V2 => (
+                header[1],
+                NE::read_u32(&header[2..6]),
+                NE::read_u16(&header[6..8]),
+            ),
+        };
+        let Ok(frame_type) = FrameType::try_from(typ & !MORE_FLAG) else {
+            return Err(ProtocolError::UnknownFrame {
+                typ,
+                seq_id,
+                len,
+                version,
+            });
+        };
+        let mut frame = Self::with_version(version, frame_type, seq_id, len);
+        frame.more = typ & MORE_FLAG != 0;
+        Ok(frame)
     }
 }
 
 impl From<Frame> for Message {
     fn from(value: Frame) -> Self {
-        let mut res = vec![value.typ.into(), value.seq_id, 0, 0];
-        NE::write_u16(&mut res[2..4], value.len);
-        res
+        let mut typ: u8 = value.typ.into();
+        if value.more {
+            typ |= MORE_FLAG;
+        }
+        match value.version {
+            FrameVersion::V1 => {
+                let mut res = vec![typ, value.seq_id as u8, 0, 0];
+                NE::write_u16(&mut res[2..4], value.len);
+                res
+            }
+            FrameVersion::V2 => {
+                let mut res = vec![VERSION_MARK | 2, typ, 0, 0, 0, 0, 0, 0];
+                NE::write_u32(&mut res[2..6], value.seq_id);
+                NE::write_u16(&mut res[6..8], value.len);
+                res
+            }
+        }
     }
 }
 
-impl From<u8> for FrameType {
-    fn from(value: u8) -> Self {
+impl TryFrom<u8> for FrameType {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
         match value {
-            1 => Self::Request,
-            2 => Self::Response,
-            0 => Self::Notify,
-            _ => panic!("Invalid value of frame type {value}"),
+            1 => Ok(Self::Request),
+            2 => Ok(Self::Response),
+            0 => Ok(Self::Notify),
+            3 => Ok(Self::Channel),
+            4 => Ok(Self::Ping),
+            5 => Ok(Self::Pong),
+            _ => Err(value),
         }
     }
 }
@@ -68,6 +237,9 @@ impl From<FrameType> for u8 {
             FrameType::Request => 1,
             FrameType::Response => 2,
             FrameType::Notify => 0,
+            FrameType::Channel => 3,
+            FrameType::Ping => 4,
+            FrameType::Pong => 5,
         }
     }
 }
@@ -87,6 +259,8 @@ impl From<idep::Project> for Project {
             path: value.path.into(),
             session_file: None,
             exists: true,
+            socket: value.socket.map(PathBuf::from),
+            last_modified: parse_time(&value.last_modified),
         }
     }
 }
@@ -106,6 +280,11 @@ impl From<&Project> for idep::Project {
         let mut s = Self::new();
         s.name = value.name.clone();
         s.path = value.path.to_str().unwrap().into();
+        s.last_modified = value.last_modified.map(format_time).unwrap_or_default();
+        if let Some(socket) = &value.socket {
+            s.state = idep::project::State::PROJECT_SERVING.into();
+            s.socket = Some(socket.to_str().unwrap().into());
+        }
         s
     }
 }
@@ -128,8 +307,97 @@ impl From<&Projects> for Response {
     }
 }
 
+impl From<&Project> for idep::response::NewProject {
+    fn from(value: &Project) -> Self {
+        let mut s = Self::new();
+        s.project = Some(value.into()).into();
+        s
+    }
+}
+
+/// Times are passed as seconds since Unix epoch, empty string when unknown.
+fn format_time(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    since_epoch.as_secs().to_string()
+}
+
+fn parse_time(time: &str) -> Option<SystemTime> {
+    let secs = time.parse().ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+fn make_new_project_update(path: &str) -> idep::OnUpdate {
+    let mut new_project = idep::on_update::NewProject::new();
+    new_project.path = path.into();
+    let mut upd = idep::OnUpdate::new();
+    upd.set_new_project(new_project);
+    upd
+}
+
+fn make_delete_project_update(path: &str) -> idep::OnUpdate {
+    let mut delete_project = idep::on_update::DeleteProject::new();
+    delete_project.path = path.into();
+    let mut upd = idep::OnUpdate::new();
+    upd.set_delete_project(delete_project);
+    upd
+}
+
+fn make_state_changed_update(path: &str, state: idep::project::State) -> idep::OnUpdate {
+    let mut state_changed = idep::on_update::ProjectStateChanged::new();
+    state_changed.path = path.into();
+    state_changed.new_state = state.into();
+    let mut upd = idep::OnUpdate::new();
+    upd.set_project_state_changed(state_changed);
+    upd
+}
+
+fn make_error(status: Status, error: String) -> Response {
+    let mut rsp = Response::new();
+    rsp.status = status.into();
+    rsp.set_error(error);
+    rsp
+}
+
+fn check_status(rsp: Response) -> io::Result<Response> {
+    let kind = match rsp.status.enum_value_or_default() {
+        Status::OK => return Ok(rsp),
+        Status::WRONG_PATH | Status::WRONG_PROJECT => io::ErrorKind::NotFound,
+        Status::WRONG_PROTOCOL => io::ErrorKind::InvalidData,
+        Status::NOT_IMPLEMENTED => io::ErrorKind::Unsupported,
+        Status::INTERNAL_ERROR => io::ErrorKind::Other,
+        Status::UNAUTHENTICATED => io::ErrorKind::PermissionDenied,
+    };
+    Err(io::Error::new(kind, rsp.error()))
+}
+
+fn make_hello(name: &str, capabilities: &[Capability]) -> idep::Hello {
+    let mut hello = idep::Hello::new();
+    hello.version = PROTOCOL_VERSION;
+    hello.name = name.into();
+    hello.capabilities = capabilities.iter().map(|&cap| cap.into()).collect();
+    hello
+}
+
+/// Returns known capabilities of peer, the unknown ones are skipped.
+fn get_capabilities(hello: &idep::Hello) -> Vec<Capability> {
+    let capabilities = hello.capabilities.iter();
+    capabilities
+        .filter_map(|cap| cap.enum_value().ok())
+        .collect()
+}
+
+fn missing_field(field: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Response does not contains '{field}' field"),
+    )
+}
+
 pub struct Client<S: Sender, R: Receiver> {
     stream: BidirectStream<S, R>,
+    subscribers: Subscribers,
+    timeout: Duration,
+    server: Option<idep::Hello>,
 }
 
 impl<S: Sender, R: Receiver> From<(S, R)> for Client<S, R> {
@@ -138,62 +406,498 @@ impl<S: Sender, R: Receiver> From<(S, R)> for Client<S, R> {
     }
 }
 
+#[derive(Clone)]
 pub struct ClientRequester {
     sender: BidirectSender,
+    timeout: Duration,
+    /// Capabilities of server, `None` when they are unknown.
+    capabilities: Option<Vec<Capability>>,
 }
 
 impl<S: Sender, R: Receiver> Client<S, R> {
     pub fn new(s: S, r: R) -> Self {
         Self {
             stream: BidirectStream::new(s, r),
+            subscribers: Default::default(),
+            timeout: DEFAULT_REQUEST_TIMEOUT,
+            server: None,
+        }
+    }
+
+    /// Creates client and introduces it to server as `name`. Fails with
+    /// `InvalidData` error if server speaks other version of protocol.
+    ///
+    /// Servers which do not know `Hello` are considered to support all
+    /// requests, so they answer `NOT_IMPLEMENTED` to the unsupported ones.
+    ///
+    /// `Hello` is sent with `V1` frames, which every server understands, the
+    /// later requests use `V2` frames if server announces
+    /// `Capability::VERSIONED_FRAMES`.
+    pub async fn connect(s: S, r: R, name: &str) -> io::Result<Self> {
+        Self::connect_with_token(s, r, name, "").await
+    }
+
+    /// Same as `connect`, but also passes `token` to server which requires
+    /// it, see `auth::TokenAuthenticator`. Fails with `PermissionDenied`
+    /// error if server does not accept the client.
+    pub async fn connect_with_token(s: S, r: R, name: &str, token: &str) -> io::Result<Self> {
+        let mut client = Self::new(s, r);
+        client.set_frame_version(FrameVersion::V1);
+        let mut requester = client.get_requester();
+        let hello = select! {
+            res = requester.hello(name, token) => res,
+            res = client.go_loop() => Err(res.err().unwrap_or(ProtocolError::Closed).into()),
+        };
+        match hello {
+            Ok(hello) => client.server = Some(hello),
+            Err(err) if Self::is_legacy_server(&err) => (),
+            Err(err) => return Err(err),
+        }
+        if client.server.is_some() && client.supports(Capability::VERSIONED_FRAMES) {
+            client.set_frame_version(FrameVersion::V2);
         }
+        Ok(client)
+    }
+
+    /// Legacy servers answer `Hello` as any unknown request, with
+    /// `UNKNOWN_REQUEST` error of `INTERNAL_ERROR` or `NOT_IMPLEMENTED`
+    /// status. Other errors are not hidden.
+    fn is_legacy_server(err: &io::Error) -> bool {
+        let kind = matches!(
+            err.kind(),
+            io::ErrorKind::Other | io::ErrorKind::Unsupported
+        );
+        kind && err.to_string() == UNKNOWN_REQUEST
+    }
+
+    /// Returns `Hello` of server, if it was received by `connect`.
+    pub fn server(&self) -> Option<&idep::Hello> {
+        self.server.as_ref()
+    }
+
+    /// Checks whether server announced `capability`. Capabilities of
+    /// servers which did not send `Hello` are unknown, so all are assumed.
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.get_requester().supports(capability)
+    }
+
+    /// Returns stream of updates sent by server, e.g. when other client
+    /// creates or deletes project. Updates are delivered while `go_loop` runs.
+    pub fn subscribe(&mut self) -> Updates {
+        let (tx, rx) = fmpsc::unbounded();
+        self.subscribers.push(tx);
+        rx
+    }
+
+    /// Sets version of frames sent to server, see `FrameVersion`. Clients
+    /// send `V1` frames unless `connect` finds that server supports `V2`.
+    pub fn set_frame_version(&mut self, version: FrameVersion) {
+        self.stream.set_frame_version(version);
+    }
+
+    /// Makes client to ping server, so dead connection fails pending
+    /// requests instead of leaving them to timeouts. See `Heartbeat`.
+    pub fn set_heartbeat(&mut self, heartbeat: Option<Heartbeat>) {
+        self.stream.set_heartbeat(heartbeat);
+    }
+
+    /// Sets time to wait for responses of requests sent by requesters got
+    /// after this call, see `ClientRequester::with_timeout`.
+    pub fn set_request_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
     }
 
     pub fn get_requester(&self) -> ClientRequester {
         ClientRequester {
             sender: self.stream.get_sender(),
+            timeout: self.timeout,
+            capabilities: self.server.as_ref().map(get_capabilities),
         }
     }
 
-    pub async fn go_loop(&mut self) -> io::Result<()> {
+    pub async fn go_loop(&mut self) -> Result<(), ProtocolError> {
         self.stream
             .go_loop(
                 Some(|req| Self::on_request(req)),
-                Some(|up| Self::on_update(up)),
+                Some(|up| Self::on_update(up, &mut self.subscribers)),
             )
             .await
     }
 
-    fn on_request(_req: idep::Request) -> io::Result<idep::Response> {
-        io::Result::Err(io::Error::new(
-            io::ErrorKind::Unsupported,
-            "Not implemented yet",
-        ))
+    async fn on_request(_req: idep::Request) -> io::Result<idep::Response> {
+        io::Result::Err(io::Error::new(io::ErrorKind::Unsupported, UNKNOWN_REQUEST))
     }
 
-    fn on_update(_upd: idep::OnUpdate) -> io::Result<()> {
-        Ok(()) // TODO (Shvedov)
+    fn on_update(upd: idep::OnUpdate, subscribers: &mut Subscribers) -> io::Result<()> {
+        subscribers.retain(|subscriber| subscriber.unbounded_send(upd.clone()).is_ok());
+        Ok(())
     }
 }
 
 impl ClientRequester {
+    /// Returns requester which waits for responses for `timeout`, e.g.
+    /// `requester.with_timeout(Duration::from_secs(1)).list_projects()`.
+    pub fn with_timeout(&self, timeout: Duration) -> Self {
+        Self {
+            timeout,
+            ..self.clone()
+        }
+    }
+
+    /// See `Client::supports`.
+    pub fn supports(&self, capability: Capability) -> bool {
+        let capabilities = self.capabilities.as_ref();
+        capabilities.is_none_or(|caps| caps.contains(&capability))
+    }
+
+    /// Fails with `Unsupported` error without bothering server which does
+    /// not support `capability`.
+    fn ensure(&self, capability: Capability) -> io::Result<()> {
+        if self.supports(capability) {
+            return Ok(());
+        }
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("Server does not support {capability:?}"),
+        ))
+    }
+
+    /// Sends request and waits for response. Responses with status other
+    /// than `OK` are converted to errors: `WRONG_PATH` and `WRONG_PROJECT`
+    /// to `NotFound`, `NOT_IMPLEMENTED` to `Unsupported` and so on. Fails
+    /// with `TimedOut` if server does not respond in time.
+    async fn request(&mut self, req: Request) -> io::Result<Response> {
+        let rsp = self.sender.send_request_timeout(req, self.timeout).await?;
+        check_status(Response::parse_from_bytes(&rsp)?)
+    }
+
+    async fn hello(&mut self, name: &str, token: &str) -> io::Result<idep::Hello> {
+        let mut hello = make_hello(name, &CLIENT_CAPABILITIES);
+        hello.token = token.into();
+        let mut req = Request::new();
+        req.set_hello(hello);
+        let rsp = self.request(req).await?;
+        if !rsp.has_hello() {
+            return Err(missing_field("hello"));
+        }
+        let hello = rsp.hello();
+        if hello.version != PROTOCOL_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Server speaks protocol version {}", hello.version),
+            ));
+        }
+        Ok(hello.clone())
+    }
+
     pub async fn list_projects(&mut self) -> io::Result<Projects> {
+        self.ensure(Capability::LIST_PROJECTS)?;
         let mut req = Request::new();
         req.set_list_projects(idep::request::ListProjects::new());
-        let rsp = self.sender.send_request(req).await?;
-        let rsp = Response::parse_from_bytes(&rsp)?;
+        let rsp = self.request(req).await?;
         if !rsp.has_list_projects() {
-            Err(io::Error::new(io::ErrorKind::InvalidData,
-                "Response does not contains 'list_projects' field"))
+            Err(missing_field("list_projects"))
         } else {
             Ok(rsp.list_projects().into())
         }
     }
+
+    pub async fn new_project(&mut self, path: &str) -> io::Result<Project> {
+        self.ensure(Capability::NEW_PROJECT)?;
+        let mut req = Request::new();
+        let mut new_project = idep::request::NewProject::new();
+        new_project.path = path.into();
+        req.set_new_project(new_project);
+        let rsp = self.request(req).await?;
+        if !rsp.has_new_project() {
+            Err(missing_field("new_project"))
+        } else {
+            let project = rsp.new_project().project.as_ref();
+            Ok(project.ok_or(missing_field("project"))?.clone().into())
+        }
+    }
+
+    pub async fn delete_project(&mut self, path: &str) -> io::Result<()> {
+        self.ensure(Capability::DELETE_PROJECT)?;
+        let mut req = Request::new();
+        let mut delete_project = idep::request::DeleteProject::new();
+        delete_project.path = path.into();
+        req.set_delete_project(delete_project);
+        let rsp = self.request(req).await?;
+        if !rsp.has_delete_project() {
+            Err(missing_field("delete_project"))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Asks server to launch headless editor for project at `path`. Returns
+    /// the socket the editor listens on.
+    pub async fn serve_project(&mut self, path: &str) -> io::Result<PathBuf> {
+        self.ensure(Capability::SERVE_PROJECT)?;
+        let mut req = Request::new();
+        let mut serve_project = idep::request::ServeProject::new();
+        serve_project.path = path.into();
+        req.set_serve_project(serve_project);
+        let rsp = self.request(req).await?;
+        if !rsp.has_serve_project() {
+            Err(missing_field("serve_project"))
+        } else {
+            Ok(rsp.serve_project().socket.clone().into())
+        }
+    }
+
+    /// Asks server to terminate headless editor of project at `path`. The
+    /// `ProjectStateChanged` update is sent when the editor exits.
+    pub async fn stop_project(&mut self, path: &str) -> io::Result<()> {
+        self.ensure(Capability::STOP_PROJECT)?;
+        let mut req = Request::new();
+        let mut stop_project = idep::request::StopProject::new();
+        stop_project.path = path.into();
+        req.set_stop_project(stop_project);
+        let rsp = self.request(req).await?;
+        if !rsp.has_stop_project() {
+            Err(missing_field("stop_project"))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Opens tunnel to editor of project at `path`, which should be served.
+    /// The data sent to channel is passed to editor socket as is and vice
+    /// versa, see `tunnel::forward`.
+    pub async fn open_tunnel(&mut self, path: &str) -> io::Result<Channel> {
+        self.ensure(Capability::TUNNEL)?;
+        let channel = self.sender.open_channel().await?;
+        let mut req = Request::new();
+        let mut open_channel = idep::request::OpenChannel::new();
+        open_channel.path = path.into();
+        open_channel.channel = channel.id();
+        req.set_open_channel(open_channel);
+        let rsp = self.request(req).await?;
+        if !rsp.has_open_channel() {
+            Err(missing_field("open_channel"))
+        } else {
+            Ok(channel)
+        }
+    }
+}
+
+/// State of server shared between all connected clients.
+pub struct Context {
+    projects: Projects,
+    headless: Headless,
+    editors: BTreeMap<PathBuf, Editor>,
+    exits: mpsc::UnboundedSender<PathBuf>,
+    exited: Option<mpsc::UnboundedReceiver<PathBuf>>,
+    notifier: broadcast::Sender<Notification>,
+    last_id: usize,
+    authenticators: Vec<Box<dyn Authenticator>>,
+    registry: Option<Registry>,
+    /// Number of registry changes, and the last one saved, see `persist`.
+    changes: u64,
+    saved: Arc<Mutex<u64>>,
+}
+
+impl Context {
+    pub fn new(projects: Projects, headless: Headless) -> Self {
+        let (exits, exited) = mpsc::unbounded_channel();
+        Self {
+            projects,
+            headless,
+            editors: Default::default(),
+            exits,
+            exited: Some(exited),
+            notifier: broadcast::channel(64).0,
+            last_id: 0,
+            authenticators: Default::default(),
+            registry: None,
+            changes: 0,
+            saved: Default::default(),
+        }
+    }
+
+    /// Makes servers to require clients to authenticate with `Hello`.
+    /// Clients are accepted if any of authenticators accepts them. Should be
+    /// called before servers sharing the context are created.
+    pub fn add_authenticator<A: Authenticator + 'static>(&mut self, authenticator: A) {
+        self.authenticators.push(Box::new(authenticator));
+    }
+
+    fn authenticate(&self, hello: &idep::Hello, peer: &Peer) -> bool {
+        let mut authenticators = self.authenticators.iter();
+        authenticators.any(|auth| auth.authenticate(hello, peer))
+    }
+
+    /// Makes context to store projects in `registry` on every change. The
+    /// projects should be merged with registry already, see
+    /// `Registry::merge`.
+    pub fn set_registry(&mut self, registry: Registry) {
+        self.registry = Some(registry);
+        self.persist();
+    }
+
+    pub fn registry(&self) -> Option<&Registry> {
+        self.registry.as_ref()
+    }
+
+    /// Saves snapshot of registry on blocking thread, so the context is not
+    /// locked while the file is written. Failure to save registry does not
+    /// fail requests, the projects are still served, but may be lost on
+    /// restart.
+    fn persist(&mut self) {
+        let Some(registry) = &mut self.registry else {
+            return;
+        };
+        registry.update(&self.projects);
+        self.changes += 1;
+        let (snapshot, change, saved) = (registry.clone(), self.changes, self.saved.clone());
+        let save = move || {
+            // Saves are serialized, and snapshots older than saved one skipped
+            let mut saved = saved.lock().unwrap();
+            if *saved >= change {
+                return;
+            }
+            *saved = change;
+            if let Err(err) = snapshot.save() {
+                let file = snapshot.file().to_str().unwrap();
+                eprintln!("Failed to save projects to '{file}': {err}");
+            }
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => drop(runtime.spawn_blocking(save)),
+            Err(_) => save(),
+        }
+    }
+
+    pub fn into_shared(self) -> SharedContext {
+        Arc::new(Mutex::new(self))
+    }
+
+    /// Tracks exits of editors launched by `ServeProject` requests: removes
+    /// their sockets and notifies all clients that projects became idle.
+    /// Should be polled all the time servers of `context` are running.
+    pub async fn supervise(context: SharedContext) {
+        let exited = context.lock().unwrap().exited.take();
+        let mut exited = exited.expect("Context is already supervised");
+        while let Some(path) = exited.recv().await {
+            context.lock().unwrap().on_editor_exit(&path);
+        }
+    }
+
+    fn on_editor_exit(&mut self, path: &PathBuf) {
+        // Exit of editor replaced already, e.g. the one which did not start
+        if self.editors.get(path).is_some_and(Editor::is_running) {
+            return;
+        }
+        self.editors.remove(path);
+        let Some(project) = self.projects.iter_mut().find(|proj| &proj.path == path) else {
+            return;
+        };
+        // Project is idle already when the editor is given up on
+        let Some(socket) = project.socket.take() else {
+            return;
+        };
+        let _ = fs::remove_file(socket);
+        let state = idep::project::State::PROJECT_IDLE;
+        let upd = make_state_changed_update(project.get_path(), state);
+        self.persist();
+        self.notify(0, upd);
+    }
+
+    /// Tracks sessions written and removed by editors, or by user, with
+    /// `watcher`: notifies all clients about new and deleted projects. Fails
+    /// only if watcher fails.
+    pub async fn watch_sessions(context: SharedContext, watcher: Watcher) -> io::Result<()> {
+        // Watcher owns its descriptor and keeps it until dropped
+        let mut watcher = unsafe { AsyncFd::register(watcher) }?;
+        loop {
+            let mut guard = watcher.readable_mut().await?;
+            let Ok(events) = guard.try_io(|watcher| watcher.get_mut().read()) else {
+                continue;
+            };
+            let mut ctx = context.lock().unwrap();
+            for event in events? {
+                ctx.on_session_event(event);
+            }
+        }
+    }
+
+    /// Served projects are kept when their sessions are removed, as well as
+    /// the projects are not duplicated when sessions of known ones appear.
+    fn on_session_event(&mut self, event: Event) {
+        let upd = match event {
+            Event::Added(project) | Event::Modified(project) => {
+                let projects = &mut self.projects;
+                match projects.iter_mut().find(|proj| proj.path == project.path) {
+                    Some(known) => {
+                        known.session_file = project.session_file;
+                        known.last_modified = known.last_modified.max(project.last_modified);
+                        return;
+                    }
+                    None => {
+                        let upd = make_new_project_update(project.get_path());
+                        projects.push(project);
+                        upd
+                    }
+                }
+            }
+            Event::Removed(project) => {
+                let projects = &mut self.projects;
+                let Some(pos) = projects.iter().position(|proj| proj.path == project.path) else {
+                    return;
+                };
+                if projects[pos].is_serving() {
+                    projects[pos].session_file = None;
+                    return;
+                }
+                projects.remove(pos);
+                make_delete_project_update(project.get_path())
+            }
+        };
+        self.persist();
+        self.notify(0, upd);
+    }
+
+    pub fn projects(&self) -> &Projects {
+        &self.projects
+    }
+
+    /// Registers new server, returns its id and receiver of notifications.
+    fn connect(&mut self) -> (usize, broadcast::Receiver<Notification>) {
+        self.last_id += 1;
+        (self.last_id, self.notifier.subscribe())
+    }
+
+    /// Sends update to all servers except the `origin` one. Servers ids start
+    /// from 1, so updates with `origin` 0 are sent to everyone.
+    fn notify(&self, origin: usize, upd: idep::OnUpdate) {
+        // Fails only when there are no servers at all
+        let _ = self.notifier.send((origin, upd));
+    }
 }
 
 pub struct Server<S: Sender, R: Receiver> {
     stream: BidirectStream<S, R>,
-    projects: Projects,
+    context: SharedContext,
+    id: usize,
+    notifications: broadcast::Receiver<Notification>,
+    session: Session,
+    tunnels: mpsc::UnboundedReceiver<tunnel::Tunnel>,
+}
+
+/// What server knows about its client.
+struct Session {
+    peer: Peer,
+    authenticated: AtomicBool,
+    sender: BidirectSender,
+    tunnels: mpsc::UnboundedSender<tunnel::Tunnel>,
+}
+
+impl Session {
+    fn is_authenticated(&self) -> bool {
+        self.authenticated.load(Ordering::Relaxed)
+    }
 }
 
 impl<S: Sender, R: Receiver> From<(S, R)> for Server<S, R> {
@@ -204,35 +908,330 @@ impl<S: Sender, R: Receiver> From<(S, R)> for Server<S, R> {
 
 impl<S: Sender, R: Receiver> Server<S, R> {
     pub fn new(stream: (S, R), projects: Projects) -> Self {
+        let context = Context::new(projects, Headless::default());
+        Self::with_context(stream, context.into_shared())
+    }
+
+    /// Creates server which shares the context with other servers, so several
+    /// clients may be served at the same time.
+    pub fn with_context(stream: (S, R), context: SharedContext) -> Self {
+        let (id, notifications) = context.lock().unwrap().connect();
+        let anonymous = context.lock().unwrap().authenticators.is_empty();
+        let mut stream = BidirectStream::new(stream.0, stream.1);
+        stream.follow_peer_version();
+        let (tx, tunnels) = mpsc::unbounded_channel();
+        let session = Session {
+            peer: Default::default(),
+            authenticated: AtomicBool::new(anonymous),
+            sender: stream.get_sender(),
+            tunnels: tx,
+        };
         Self {
-            stream: BidirectStream::new(stream.0, stream.1),
-            projects,
+            stream,
+            context,
+            id,
+            notifications,
+            session,
+            tunnels,
         }
     }
 
+    /// Sets credentials of client known from transport, e.g. from Unix
+    /// socket. They are checked by authenticators of context.
+    pub fn set_peer(&mut self, peer: Peer) {
+        self.session.peer = peer;
+    }
+
+    /// Makes server to ping client, so dead clients are dropped. See
+    /// `Heartbeat`.
+    pub fn set_heartbeat(&mut self, heartbeat: Option<Heartbeat>) {
+        self.stream.set_heartbeat(heartbeat);
+    }
+
     pub fn list_projects(&self) -> io::Result<Message> {
-        let rsp: Response = (&self.projects).into();
+        let rsp: Response = self.context.lock().unwrap().projects().into();
         Ok(rsp.write_to_bytes()?)
     }
 
-    pub async fn next(&mut self) -> io::Result<()> {
-        self.stream
-            .go_loop(
-                Some(|req| Self::on_request(req, &mut self.projects)),
+    pub async fn next(&mut self) -> Result<(), ProtocolError> {
+        let id = self.id;
+        let sender = self.stream.get_sender();
+        let session = &self.session;
+        select! {
+            res = self.stream.go_loop(
+                Some(|req| Self::on_request(req, &self.context, id, session)),
                 Some(|up| Self::on_update(up)),
-            )
-            .await
+            ) => res,
+            res = Self::forward_notifications(id, &mut self.notifications, sender, session) => res,
+            res = tunnel::serve(&mut self.tunnels) => res,
+        }
+    }
+
+    async fn forward_notifications(
+        id: usize,
+        notifications: &mut broadcast::Receiver<Notification>,
+        mut sender: BidirectSender,
+        session: &Session,
+    ) -> Result<(), ProtocolError> {
+        loop {
+            match notifications.recv().await {
+                Ok((origin, upd)) if origin != id && session.is_authenticated() => {
+                    sender.send_update(upd).await?
+                }
+                Ok(_) => (),
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    eprintln!("Client {id} missed {missed} updates")
+                }
+                // Context keeps the sender while server is alive
+                Err(broadcast::error::RecvError::Closed) => return Err(ProtocolError::Closed),
+            }
+        }
+    }
+
+    /// Handles request of client. Requests are handled as they arrive, so
+    /// the ones following `Hello` are authenticated by it. Only requests
+    /// which wait for something, like `ServeProject`, are finished later,
+    /// and they do not hold the context locked meanwhile.
+    fn on_request<'a>(
+        req: idep::Request,
+        ctx: &'a SharedContext,
+        id: usize,
+        session: &'a Session,
+    ) -> impl Future<Output = io::Result<idep::Response>> + 'a {
+        // Requests left to the future are the `Err` ones
+        let handled = if req.has_serve_project() && session.is_authenticated() {
+            Err(req)
+        } else {
+            let mut ctx = ctx.lock().unwrap();
+            Ok(Self::handle(req, &mut ctx, session))
+        };
+        async move {
+            let (rsp, upd) = match handled {
+                Ok(handled) => handled?,
+                Err(req) => Self::serve_project(req.serve_project(), ctx).await,
+            };
+            // Only changes of projects are notified about
+            if let Some(upd) = upd {
+                let mut ctx = ctx.lock().unwrap();
+                ctx.persist();
+                ctx.notify(id, upd);
+            }
+            Ok(rsp)
+        }
     }
 
-    fn on_request(req: idep::Request, prj: &mut Projects) -> io::Result<idep::Response> {
-        if req.has_list_projects() {
-            Ok(Response::from(prj.as_ref()))
+    fn handle(req: idep::Request, ctx: &mut Context, session: &Session) -> io::Result<Handled> {
+        let handled = if req.has_hello() {
+            Self::hello(req.hello(), ctx, session)
+        } else if !session.is_authenticated() {
+            let err = "Client is not authenticated".to_string();
+            (make_error(Status::UNAUTHENTICATED, err), None)
+        } else if req.has_list_projects() {
+            (Response::from(ctx.projects()), None)
+        } else if req.has_new_project() {
+            Self::new_project(req.new_project(), ctx)
+        } else if req.has_delete_project() {
+            Self::delete_project(req.delete_project(), ctx)
+        } else if req.has_stop_project() {
+            Self::stop_project(req.stop_project(), ctx)
+        } else if req.has_open_channel() {
+            Self::open_channel(req.open_channel(), ctx, session)
         } else {
-            io::Result::Err(io::Error::new(
-                io::ErrorKind::Unsupported,
-                "Not implemented yet",
-            ))
+            return io::Result::Err(io::Error::new(io::ErrorKind::Unsupported, UNKNOWN_REQUEST));
+        };
+        Ok(handled)
+    }
+
+    fn hello(req: &idep::Hello, ctx: &Context, session: &Session) -> Handled {
+        if req.version != PROTOCOL_VERSION {
+            let err = format!(
+                "Protocol version {} is not supported, expected {PROTOCOL_VERSION}",
+                req.version
+            );
+            return (make_error(Status::WRONG_PROTOCOL, err), None);
+        }
+        if !session.is_authenticated() {
+            if !ctx.authenticate(req, &session.peer) {
+                let err = format!("Client '{}' is not authenticated", req.name);
+                return (make_error(Status::UNAUTHENTICATED, err), None);
+            }
+            session.authenticated.store(true, Ordering::Relaxed);
+        }
+        let mut rsp = Response::new();
+        rsp.set_hello(make_hello(SERVER_NAME, &SERVER_CAPABILITIES));
+        (rsp, None)
+    }
+
+    fn new_project(req: &idep::request::NewProject, ctx: &mut Context) -> Handled {
+        let path = match fs::canonicalize(&req.path) {
+            Ok(path) if path.is_dir() && path.file_name().is_some() => path,
+            _ => {
+                let err = format!("No such directory '{}'", req.path);
+                return (make_error(Status::WRONG_PATH, err), None);
+            }
+        };
+        let (project, upd) = match ctx.projects.iter().find(|proj| proj.path == path) {
+            Some(project) => (project.clone(), None),
+            None => {
+                let project = Project::from_path(path.to_str().unwrap());
+                ctx.projects.push(project.clone());
+                let upd = make_new_project_update(project.get_path());
+                (project, Some(upd))
+            }
+        };
+        let mut rsp = Response::new();
+        rsp.set_new_project((&project).into());
+        (rsp, upd)
+    }
+
+    fn delete_project(req: &idep::request::DeleteProject, ctx: &mut Context) -> Handled {
+        let projects = &mut ctx.projects;
+        let Some(pos) = projects.iter().position(|proj| proj.get_path() == req.path) else {
+            let err = format!("No such project '{}'", req.path);
+            return (make_error(Status::WRONG_PROJECT, err), None);
+        };
+        if let Err(err) = projects[pos].rm() {
+            let err = format!("Failed to remove session of '{}': {err}", req.path);
+            return (make_error(Status::INTERNAL_ERROR, err), None);
+        }
+        let mut project = projects.remove(pos);
+        // Editor exit is not tracked anymore, as the project is gone
+        if let Some(socket) = project.socket.take() {
+            let _ = fs::remove_file(socket);
+        }
+        if let Some(editor) = ctx.editors.remove(&project.path) {
+            editor.stop();
+        }
+        let mut delete_project = idep::response::DeleteProject::new();
+        delete_project.path = req.path.clone();
+        let mut rsp = Response::new();
+        rsp.set_delete_project(delete_project);
+        (rsp, Some(make_delete_project_update(&req.path)))
+    }
+
+    /// Launches editor and waits until it listens, so client could connect
+    /// to the returned socket right away. Fails if editor exits or does not
+    /// start in time, the latter is stopped then.
+    async fn serve_project(req: &idep::request::ServeProject, ctx: &SharedContext) -> Handled {
+        let (rsp, upd) = Self::launch_editor(req, &mut ctx.lock().unwrap());
+        if !rsp.has_serve_project() {
+            return (rsp, upd);
+        }
+        let socket = PathBuf::from(&rsp.serve_project().socket);
+        let timeout = ctx.lock().unwrap().headless.start_timeout;
+        match Self::wait_for_editor(&req.path, &socket, timeout, ctx).await {
+            Ok(()) => (rsp, upd),
+            Err(err) => {
+                let err = format!("Failed to serve '{}': {err}", req.path);
+                let upd = Self::give_up_editor(&req.path, &socket, ctx);
+                (make_error(Status::INTERNAL_ERROR, err), upd)
+            }
+        }
+    }
+
+    async fn wait_for_editor(
+        path: &str,
+        socket: &Path,
+        timeout: Duration,
+        ctx: &SharedContext,
+    ) -> Result<(), String> {
+        let started = time::Instant::now();
+        while !socket.exists() {
+            if !Self::is_serving(path, socket, ctx) {
+                return Err(String::from("editor exited"));
+            }
+            if started.elapsed() >= timeout {
+                return Err(format!("editor did not listen in {timeout:?}"));
+            }
+            time::sleep(EDITOR_POLL_INTERVAL).await;
+        }
+        Ok(())
+    }
+
+    /// Stops editor which did not start and makes project idle. Returns the
+    /// update about it, unless the project is reset already.
+    fn give_up_editor(path: &str, socket: &Path, ctx: &SharedContext) -> Option<idep::OnUpdate> {
+        let mut ctx = ctx.lock().unwrap();
+        let ctx = &mut *ctx;
+        let project = ctx
+            .projects
+            .iter_mut()
+            .find(|proj| proj.get_path() == path && proj.socket.as_deref() == Some(socket))?;
+        project.socket = None;
+        let _ = fs::remove_file(socket);
+        if let Some(editor) = ctx.editors.remove(&project.path) {
+            editor.stop();
+        }
+        let state = idep::project::State::PROJECT_IDLE;
+        Some(make_state_changed_update(path, state))
+    }
+
+    fn is_serving(path: &str, socket: &Path, ctx: &SharedContext) -> bool {
+        let ctx = ctx.lock().unwrap();
+        let mut projects = ctx.projects.iter();
+        projects.any(|proj| proj.get_path() == path && proj.socket.as_deref() == Some(socket))
+    }
+
+    fn launch_editor(req: &idep::request::ServeProject, ctx: &mut Context) -> Handled {
+        let projects = &mut ctx.projects;
+        let Some(project) = projects.iter_mut().find(|proj| proj.get_path() == req.path) else {
+            let err = format!("No such project '{}'", req.path);
+            return (make_error(Status::WRONG_PROJECT, err), None);
+        };
+        let mut upd = None;
+        if !project.is_serving() {
+            let socket = ctx.headless.socket_for(project);
+            match ctx.headless.launch(project, &socket, &ctx.exits) {
+                Ok(editor) => ctx.editors.insert(project.path.clone(), editor),
+                Err(err) => {
+                    let err = format!("Failed to launch '{}': {err}", ctx.headless.program);
+                    return (make_error(Status::INTERNAL_ERROR, err), None);
+                }
+            };
+            project.socket = Some(socket);
+            project.opened_at(SystemTime::now());
+            let state = idep::project::State::PROJECT_SERVING;
+            upd = Some(make_state_changed_update(&req.path, state));
+        }
+        let mut serve_project = idep::response::ServeProject::new();
+        serve_project.socket = project.socket.as_ref().unwrap().to_str().unwrap().into();
+        let mut rsp = Response::new();
+        rsp.set_serve_project(serve_project);
+        (rsp, upd)
+    }
+
+    fn stop_project(req: &idep::request::StopProject, ctx: &mut Context) -> Handled {
+        let Some(project) = ctx.projects.iter().find(|proj| proj.get_path() == req.path) else {
+            let err = format!("No such project '{}'", req.path);
+            return (make_error(Status::WRONG_PROJECT, err), None);
+        };
+        if let Some(editor) = ctx.editors.remove(&project.path) {
+            editor.stop();
+        }
+        let mut rsp = Response::new();
+        rsp.set_stop_project(idep::response::StopProject::new());
+        (rsp, None)
+    }
+
+    fn open_channel(req: &idep::request::OpenChannel, ctx: &Context, session: &Session) -> Handled {
+        let project = ctx.projects.iter().find(|proj| proj.get_path() == req.path);
+        let Some(socket) = project.and_then(|proj| proj.socket.clone()) else {
+            let err = format!("Project '{}' is not served", req.path);
+            return (make_error(Status::WRONG_PROJECT, err), None);
+        };
+        match session.sender.accept_channel(req.channel) {
+            Ok(channel) => {
+                // Fails only if server is stopping
+                let _ = session.tunnels.send((channel, socket));
+            }
+            Err(err) => {
+                let err = format!("Failed to open channel {}: {err}", req.channel);
+                return (make_error(Status::INTERNAL_ERROR, err), None);
+            }
         }
+        let mut rsp = Response::new();
+        rsp.set_open_channel(idep::response::OpenChannel::new());
+        (rsp, None)
     }
 
     fn on_update(_upd: idep::OnUpdate) -> io::Result<()> {
@@ -0,0 +1,262 @@
+use super::streams::Heartbeat;
+use super::{idep, Client, ClientRequester, ProtocolError, Receiver, Sender};
+use super::{make_delete_project_update, make_new_project_update, make_state_changed_update};
+use super::{Subscribers, Updates, DEFAULT_REQUEST_TIMEOUT};
+use futures::channel::mpsc as fmpsc;
+use futures::StreamExt as _;
+use idep::project::State;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io;
+use std::time::Duration;
+use tokio::{select, time};
+
+/// Delays between attempts to reconnect, doubled after each failed attempt.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+    /// Gives up after so many failed attempts in a row. Retries forever when
+    /// `None`.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(10),
+            max_attempts: None,
+        }
+    }
+}
+
+/// Client which reconnects to server when connection is lost, e.g. because
+/// server restarted.
+///
+/// Subscribers get updates of all connections. On reconnection the projects
+/// are listed again, and the changes missed while disconnected are sent to
+/// subscribers as synthetic updates, so their view of projects stays
+/// consistent.
+///
+/// Requesters are bound to connection, the ones got before reconnection fail
+/// with `ConnectionAborted` error, so they should be taken again.
+pub struct ReconnectingClient<S: Sender, R: Receiver, C> {
+    connect: C,
+    name: String,
+    token: String,
+    backoff: Backoff,
+    heartbeat: Option<Heartbeat>,
+    timeout: Duration,
+    client: Client<S, R>,
+    updates: Updates,
+    /// Known projects by path and whether they are served.
+    projects: BTreeMap<String, bool>,
+    subscribers: Subscribers,
+}
+
+impl<S, R, C, F> ReconnectingClient<S, R, C>
+where
+    S: Sender,
+    R: Receiver,
+    C: FnMut() -> F,
+    F: Future<Output = io::Result<(S, R)>>,
+{
+    /// Opens stream with `connect` and introduces client to server as
+    /// `name`, see `Client::connect`. Fails if the first connection fails.
+    pub async fn connect(connect: C, name: &str) -> io::Result<Self> {
+        Self::connect_with_token(connect, name, "").await
+    }
+
+    /// Same as `connect`, but also passes `token`, see
+    /// `Client::connect_with_token`.
+    pub async fn connect_with_token(mut connect: C, name: &str, token: &str) -> io::Result<Self> {
+        let timeout = DEFAULT_REQUEST_TIMEOUT;
+        let (client, updates, projects) =
+            Self::open(&mut connect, name, token, None, timeout).await?;
+        let projects = projects.into_iter().collect();
+        Ok(Self {
+            connect,
+            name: name.into(),
+            token: token.into(),
+            backoff: Default::default(),
+            heartbeat: None,
+            timeout,
+            client,
+            updates,
+            projects,
+            subscribers: Default::default(),
+        })
+    }
+
+    pub fn set_backoff(&mut self, backoff: Backoff) {
+        self.backoff = backoff;
+    }
+
+    /// See `Client::set_heartbeat`. Applies to the following connections too.
+    pub fn set_heartbeat(&mut self, heartbeat: Option<Heartbeat>) {
+        self.heartbeat = heartbeat;
+        self.client.set_heartbeat(heartbeat);
+    }
+
+    /// See `Client::set_request_timeout`.
+    pub fn set_request_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+        self.client.set_request_timeout(timeout);
+    }
+
+    /// Returns client of the current connection.
+    pub fn client(&self) -> &Client<S, R> {
+        &self.client
+    }
+
+    /// Returns requester of the current connection.
+    pub fn get_requester(&self) -> ClientRequester {
+        self.client.get_requester()
+    }
+
+    /// Returns stream of updates, which outlives connections.
+    pub fn subscribe(&mut self) -> Updates {
+        let (tx, rx) = fmpsc::unbounded();
+        self.subscribers.push(tx);
+        rx
+    }
+
+    /// Serves connection and reconnects when it is lost. Fails only when
+    /// server could not be reached with `Backoff::max_attempts` attempts or
+    /// rejects the client.
+    pub async fn go_loop(&mut self) -> Result<(), ProtocolError> {
+        loop {
+            let subscribers = &mut self.subscribers;
+            let forward = Self::forward_updates(&mut self.updates, &mut self.projects, subscribers);
+            select! {
+                _ = self.client.go_loop() => (),
+                _ = forward => (),
+            }
+            self.reconnect().await?;
+        }
+    }
+
+    async fn reconnect(&mut self) -> Result<(), ProtocolError> {
+        let mut delay = self.backoff.initial;
+        let mut attempts = 0;
+        loop {
+            time::sleep(delay).await;
+            attempts += 1;
+            let res = Self::open(
+                &mut self.connect,
+                &self.name,
+                &self.token,
+                self.heartbeat,
+                self.timeout,
+            );
+            match res.await {
+                Ok((client, updates, projects)) => {
+                    self.client = client;
+                    self.updates = updates;
+                    self.resync(projects.into_iter().collect());
+                    return Ok(());
+                }
+                Err(err) if Self::is_fatal(&err) => return Err(err.into()),
+                Err(err) if self.backoff.max_attempts.is_some_and(|max| attempts >= max) => {
+                    return Err(err.into())
+                }
+                Err(_) => delay = (delay * 2).min(self.backoff.max),
+            }
+        }
+    }
+
+    /// Errors which are not fixed by retrying: client is not accepted or
+    /// server speaks other protocol.
+    fn is_fatal(err: &io::Error) -> bool {
+        matches!(
+            err.kind(),
+            io::ErrorKind::PermissionDenied | io::ErrorKind::InvalidData
+        )
+    }
+
+    /// Connects to server and lists its projects. Updates are subscribed
+    /// before listing, so none of them is missed.
+    async fn open(
+        connect: &mut C,
+        name: &str,
+        token: &str,
+        heartbeat: Option<Heartbeat>,
+        timeout: Duration,
+    ) -> io::Result<(Client<S, R>, Updates, Vec<(String, bool)>)> {
+        let (s, r) = connect().await?;
+        let mut client = Client::connect_with_token(s, r, name, token).await?;
+        client.set_heartbeat(heartbeat);
+        client.set_request_timeout(timeout);
+        let updates = client.subscribe();
+        let mut requester = client.get_requester();
+        let projects = select! {
+            res = requester.list_projects() => res?,
+            res = client.go_loop() => return Err(res.err().unwrap_or(ProtocolError::Closed).into()),
+        };
+        let projects = projects
+            .iter()
+            .map(|proj| (proj.get_path().to_string(), proj.is_serving()));
+        Ok((client, updates, projects.collect()))
+    }
+
+    /// Replaces known projects with `fresh` ones and notifies subscribers
+    /// about the difference.
+    fn resync(&mut self, fresh: BTreeMap<String, bool>) {
+        let mut diff = Vec::new();
+        let known = &self.projects;
+        for path in known.keys().filter(|path| !fresh.contains_key(*path)) {
+            diff.push(make_delete_project_update(path));
+        }
+        for (path, &serving) in &fresh {
+            let known = self.projects.get(path).copied();
+            if known.is_none() {
+                diff.push(make_new_project_update(path));
+            }
+            if known.unwrap_or(false) != serving {
+                let state = match serving {
+                    true => State::PROJECT_SERVING,
+                    false => State::PROJECT_IDLE,
+                };
+                diff.push(make_state_changed_update(path, state));
+            }
+        }
+        self.projects = fresh;
+        for upd in diff {
+            notify(&mut self.subscribers, upd);
+        }
+    }
+
+    async fn forward_updates(
+        updates: &mut Updates,
+        projects: &mut BTreeMap<String, bool>,
+        subscribers: &mut Subscribers,
+    ) {
+        // Client keeps the sender while connection is alive
+        while let Some(upd) = updates.next().await {
+            if Self::apply(projects, &upd) {
+                notify(subscribers, upd);
+            }
+        }
+    }
+
+    /// Updates known projects, returns whether `upd` changed them.
+    fn apply(projects: &mut BTreeMap<String, bool>, upd: &idep::OnUpdate) -> bool {
+        if upd.has_new_project() {
+            let path = &upd.new_project().path;
+            projects.insert(path.clone(), false).is_none()
+        } else if upd.has_delete_project() {
+            projects.remove(&upd.delete_project().path).is_some()
+        } else if upd.has_project_state_changed() {
+            let changed = upd.project_state_changed();
+            let serving = changed.new_state.enum_value() == Ok(State::PROJECT_SERVING);
+            projects.insert(changed.path.clone(), serving) != Some(serving)
+        } else {
+            false
+        }
+    }
+}
+
+fn notify(subscribers: &mut Subscribers, upd: idep::OnUpdate) {
+    subscribers.retain(|subscriber| subscriber.unbounded_send(upd.clone()).is_ok());
+}
@@ -1,15 +1,16 @@
-use super::{idep, Frame, FrameType};
+use super::{idep, Frame, FrameType, FrameVersion, ProtocolError, MAX_FRAME_LEN, MAX_MESSAGE_LEN};
 use super::{Message, Receiver, Sender};
 use protobuf::Message as _;
 use std::collections::VecDeque;
-use std::{cell::RefCell, rc::Rc};
 use std::io;
-use tokio::sync::mpsc;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _};
+use tokio::sync::{mpsc, Mutex};
 
 type MessageSender = mpsc::Sender<Message>;
 type MessageReceiver = mpsc::Receiver<Message>;
 
-pub type VirtualStream = (Rc<RefCell<MessageSender>>, Rc<RefCell<MessageReceiver>>);
+pub type VirtualStream = (Arc<Mutex<MessageSender>>, Arc<Mutex<MessageReceiver>>);
 
 pub struct VirtualStreamBuilder {
     left: VirtualStream,
@@ -18,25 +19,30 @@ pub struct VirtualStreamBuilder {
 
 impl Sender for MessageSender {
     async fn send(&mut self, msg: Message) -> io::Result<()> {
-        Ok(Self::send(self, msg).await.unwrap())
+        Self::send(self, msg)
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Connection closed by peer"))
     }
 }
 
-impl Sender for Rc<RefCell<MessageSender>> {
+impl Sender for Arc<Mutex<MessageSender>> {
     async fn send(&mut self, msg: Message) -> io::Result<()> {
-        Sender::send(&mut *self.borrow_mut(), msg).await
+        Sender::send(&mut *self.lock().await, msg).await
     }
 }
 
 impl Receiver for MessageReceiver {
     async fn recv(&mut self) -> io::Result<Message> {
-        Ok(Self::recv(self).await.unwrap())
+        Self::recv(self).await.ok_or(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "Connection closed by peer",
+        ))
     }
 }
 
-impl Receiver for Rc<RefCell<MessageReceiver>> {
+impl Receiver for Arc<Mutex<MessageReceiver>> {
     async fn recv(&mut self) -> io::Result<Message> {
-        Receiver::recv(&mut *self.borrow_mut()).await
+        Receiver::recv(&mut *self.lock().await).await
     }
 }
 
@@ -45,8 +51,8 @@ impl VirtualStreamBuilder {
         let (ltx, lrx) = mpsc::channel::<Message>(16);
         let (rtx, rrx) = mpsc::channel::<Message>(16);
         Self {
-            left: (Rc::new(RefCell::new(ltx)), Rc::new(RefCell::new(rrx))),
-            right: (Rc::new(RefCell::new(rtx)), Rc::new(RefCell::new(lrx))),
+            left: (Arc::new(Mutex::new(ltx)), Arc::new(Mutex::new(rrx))),
+            right: (Arc::new(Mutex::new(rtx)), Arc::new(Mutex::new(lrx))),
         }
     }
     pub fn make_left(&self) -> VirtualStream {
@@ -66,6 +72,25 @@ impl VirtualStreamBuilder {
     }
 }
 
+const READ_CHUNK_SIZE: usize = 4096;
+
+async fn write_message<W: AsyncWrite + Unpin>(writer: &mut W, msg: Message) -> io::Result<()> {
+    writer.write_all(&msg).await
+}
+
+async fn read_message<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Message> {
+    let mut msg = vec![0; READ_CHUNK_SIZE];
+    let len = reader.read(&mut msg).await?;
+    if len == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "Connection closed by peer",
+        ));
+    }
+    msg.truncate(len);
+    Ok(msg)
+}
+
 pub struct HandyReceiver<R: Receiver> {
     receiver: R,
     cache: VecDeque<u8>,
@@ -86,10 +111,15 @@ impl<R: Receiver> HandyReceiver<R> {
         Ok(())
     }
 
-    pub async fn read_exact(&mut self, len: usize) -> io::Result<Message> {
+    pub async fn fill(&mut self, len: usize) -> io::Result<&VecDeque<u8>> {
         while self.cache.len() < len {
             self.get_more().await?;
         }
+        Ok(&self.cache)
+    }
+
+    pub async fn read_exact(&mut self, len: usize) -> io::Result<Message> {
+        self.fill(len).await?;
         Ok(self.cache.drain(0..len).collect())
     }
 }
@@ -105,6 +135,7 @@ impl<R: Receiver> Receiver for HandyReceiver<R> {
 
 pub struct PackageReceiver<R: Receiver> {
     receiver: HandyReceiver<R>,
+    partial: Option<Message>,
 }
 
 impl<R: Receiver> Receiver for PackageReceiver<R> {
@@ -115,30 +146,68 @@ impl<R: Receiver> Receiver for PackageReceiver<R> {
 
 impl<R: Receiver> From<HandyReceiver<R>> for PackageReceiver<R> {
     fn from(stream: HandyReceiver<R>) -> Self {
-        Self { receiver: stream }
+        Self {
+            receiver: stream,
+            partial: None,
+        }
     }
 }
 
 impl<R: Receiver> From<R> for PackageReceiver<R> {
     fn from(stream: R) -> Self {
-        Self {
-            receiver: stream.into(),
-        }
+        HandyReceiver::from(stream).into()
     }
 }
 
 impl<R: Receiver> PackageReceiver<R> {
-    pub async fn read_package(&mut self) -> io::Result<(FrameType, u8, Message)> {
-        let frame = self.receiver.read_exact(Frame::len()).await?;
-        let frame = Frame::from(&frame);
-        let msg = self.receiver.read_exact(frame.len as usize).await?;
-        Ok((frame.typ, frame.seq_id, msg))
+    /// Reads whole message, joining frames of continued messages. The
+    /// returned frame is the last one, so its `len` is the length of last part.
+    ///
+    /// Frames are consumed only when cached completely, and the parts of
+    /// continued message are kept in `self`, so the returned future may be
+    /// safely dropped (e.g. by `select!`) at any await point.
+    ///
+    /// Frames of unknown type are skipped and reported as
+    /// `ProtocolError::UnknownFrame`, so reading may be continued after it.
+    pub async fn read_package(&mut self) -> Result<(Frame, Message), ProtocolError> {
+        loop {
+            let (frame, mut msg) = self.read_frame().await?;
+            let msg = match self.partial.take() {
+                Some(mut partial) => {
+                    partial.append(&mut msg);
+                    partial
+                }
+                None => msg,
+            };
+            if msg.len() > MAX_MESSAGE_LEN {
+                return Err(ProtocolError::TooLarge);
+            }
+            if !frame.more {
+                return Ok((frame, msg));
+            }
+            self.partial = Some(msg);
+        }
+    }
+
+    async fn read_frame(&mut self) -> Result<(Frame, Message), ProtocolError> {
+        let version = FrameVersion::detect(self.receiver.fill(1).await?[0])?;
+        let header_len = version.header_len();
+        let (len, frame) = match Frame::parse(self.receiver.fill(header_len).await?) {
+            Ok(frame) => (frame.len, Ok(frame)),
+            Err(err @ ProtocolError::UnknownFrame { len, .. }) => (len, Err(err)),
+            Err(err) => return Err(err),
+        };
+        self.receiver.fill(header_len + len as usize).await?;
+        self.receiver.read_exact(header_len).await?;
+        let msg = self.receiver.read_exact(len as usize).await?;
+        Ok((frame?, msg))
     }
 }
 
 pub struct PackageSender<S: Sender> {
     sender: S,
-    seq_id: u8,
+    seq_id: u32,
+    version: FrameVersion,
 }
 
 impl<S: Sender> From<S> for PackageSender<S> {
@@ -146,44 +215,87 @@ impl<S: Sender> From<S> for PackageSender<S> {
         Self {
             sender: value,
             seq_id: 0,
+            version: FrameVersion::V1,
         }
     }
 }
 
 impl<S: Sender> PackageSender<S> {
+    /// Sets version of frames to send. Frames are `FrameVersion::V1` by
+    /// default, as every peer understands them, `V2` should be used only
+    /// with peers known to support it.
+    pub fn set_version(&mut self, version: FrameVersion) {
+        self.version = version;
+        self.seq_id = self.seq_id.min(version.max_seq_id());
+    }
+
+    pub fn version(&self) -> FrameVersion {
+        self.version
+    }
+
+    pub async fn write_frame(&mut self, frame: Frame, msg: Message) -> io::Result<()> {
+        self.sender.send(frame.into()).await?;
+        self.sender.send(msg).await?;
+        Ok(())
+    }
+
     pub async fn write_package(
         &mut self,
         typ: FrameType,
-        seq_id: u8,
+        seq_id: u32,
         msg: Message,
     ) -> io::Result<()> {
-        self.sender
-            .send(Frame::new(typ, seq_id, msg.len() as u16).into())
-            .await?;
-        self.sender.send(msg).await?;
-        Ok(())
+        self.write_package_as(self.version, typ, seq_id, msg).await
     }
 
-    pub async fn write_request(&mut self, req: idep::Request) -> io::Result<u8> {
-        let seq_id = self.next_seq_id();
+    /// Writes message with frames of `version`. Messages longer than
+    /// `MAX_FRAME_LEN` are split into several continued frames.
+    pub async fn write_package_as(
+        &mut self,
+        version: FrameVersion,
+        typ: FrameType,
+        seq_id: u32,
+        msg: Message,
+    ) -> io::Result<()> {
+        if msg.len() > MAX_MESSAGE_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Message exceeds {MAX_MESSAGE_LEN} bytes"),
+            ));
+        }
+        let mut rest = &msg[..];
+        loop {
+            let len = rest.len().min(MAX_FRAME_LEN);
+            let mut frame = Frame::with_version(version, typ, seq_id, len as u16);
+            frame.more = len < rest.len();
+            self.write_frame(frame, rest[..len].to_vec()).await?;
+            rest = &rest[len..];
+            if rest.is_empty() {
+                return Ok(());
+            }
+        }
+    }
+
+    pub async fn write_request(&mut self, seq_id: u32, req: idep::Request) -> io::Result<()> {
         self.write_package(FrameType::Request, seq_id, req.write_to_bytes()?)
-            .await?;
-        Ok(seq_id)
+            .await
     }
 
-    pub async fn write_update(&mut self, upd: idep::OnUpdate) -> io::Result<u8> {
+    pub async fn write_update(&mut self, upd: idep::OnUpdate) -> io::Result<u32> {
         let seq_id = self.next_seq_id();
         self.write_package(FrameType::Notify, seq_id, upd.write_to_bytes()?)
             .await?;
         Ok(seq_id)
     }
 
-    pub async fn send_response(&mut self, seq_id: u8, rsp: Message) -> io::Result<()> {
+    pub async fn send_response(&mut self, seq_id: u32, rsp: Message) -> io::Result<()> {
         self.write_package(FrameType::Response, seq_id, rsp).await
     }
 
-    fn next_seq_id(&mut self) -> u8 {
-        if self.seq_id >= 0xff {
+    /// Returns next sequence id. Ids are never zero and wrap at the maximum
+    /// allowed by frame version, so caller should check for collisions.
+    pub fn next_seq_id(&mut self) -> u32 {
+        if self.seq_id >= self.version.max_seq_id() {
             self.seq_id = 0;
         }
         self.seq_id += 1;
@@ -192,5 +304,9 @@ impl<S: Sender> PackageSender<S> {
 }
 
 mod bidir;
+pub mod tcp;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod unix;
 
-pub use bidir::{BidirectStream, BidirectSender};
+pub use bidir::{BidirectStream, BidirectSender, Channel, Heartbeat};
@@ -1,19 +1,84 @@
-use super::{idep, FrameType, Message, PackageReceiver, PackageSender, Receiver, Sender};
+use super::{idep, Frame, FrameType, FrameVersion, Message, PackageReceiver, PackageSender};
+use super::{ProtocolError, Receiver, Sender};
+use futures::stream::{FuturesUnordered, StreamExt as _};
+use futures::FutureExt as _;
+use idep::response::Status;
 use protobuf::Message as _;
 use tokio::{
     select,
     sync::{mpsc, oneshot},
+    time,
 };
 
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
 use std::io;
+use std::time::Duration;
 
 type CachedRequest = oneshot::Sender<io::Result<Message>>;
-pub trait OnRequestH = FnMut(idep::Request) -> io::Result<idep::Response>;
+type ChannelData = mpsc::Sender<Message>;
+/// Data of channel which did not fit into its queue.
+type Stalled = (u32, ChannelData, Message);
+
+/// Messages of channel received, but not taken by its consumer yet. The loop
+/// of stream stops reading frames when this many are queued.
+const CHANNEL_CAPACITY: usize = 16;
+
+/// Ids of requests nobody waits for are kept for their late responses, the
+/// oldest ones are forgotten beyond this many. So peer which never answers
+/// does not exhaust ids, e.g. the 255 ones of `FrameVersion::V1`.
+const MAX_PRUNED: usize = 64;
 pub trait OnUpdateH = FnMut(idep::OnUpdate) -> io::Result<()>;
 
+/// Handler of requests. It returns future, so requests are processed
+/// concurrently and responses are sent as soon as they are ready, not in the
+/// order of requests. Implemented for closures returning futures, e.g.
+/// `|req| async move { handle(req).await }`.
+pub trait OnRequestH {
+    type Future: Future<Output = io::Result<idep::Response>>;
+
+    fn on_request(&mut self, req: idep::Request) -> Self::Future;
+}
+
+impl<F, Fut> OnRequestH for F
+where
+    F: FnMut(idep::Request) -> Fut,
+    Fut: Future<Output = io::Result<idep::Response>>,
+{
+    type Future = Fut;
+
+    fn on_request(&mut self, req: idep::Request) -> Fut {
+        self(req)
+    }
+}
+
+/// Makes `BidirectStream` to ping peer every `interval` and to fail when
+/// `max_missed` pings in a row are not answered. Peer should answer `Ping`
+/// frames, i.e. announce `Capability::HEARTBEAT`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Heartbeat {
+    pub interval: Duration,
+    pub max_missed: u32,
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(15),
+            max_missed: 3,
+        }
+    }
+}
+
 enum Notice {
     RequestTask((idep::Request, CachedRequest)),
     UpdateTask(idep::OnUpdate),
+    /// Some requesters stopped waiting for responses.
+    Prune,
+    /// Registers channel with the next free id.
+    OpenChannel((ChannelData, oneshot::Sender<io::Result<u32>>)),
+    /// Sends data of channel, the empty one closes the channel.
+    ChannelData((u32, Message)),
 }
 
 pub struct BidirectStream<S, R>
@@ -23,7 +88,21 @@ where
 {
     sender: PackageSender<S>,
     receiver: PackageReceiver<R>,
-    requests: std::collections::BTreeMap<u8, CachedRequest>,
+    requests: BTreeMap<u32, CachedRequest>,
+    /// Ids of requests nobody waits for, their responses are dropped. The
+    /// oldest come first.
+    pruned: VecDeque<u32>,
+    /// Number of pruned requests forgotten before their responses arrived,
+    /// see `MAX_PRUNED`.
+    forgotten: usize,
+    channels: BTreeMap<u32, ChannelData>,
+    last_channel: u32,
+    accepted: mpsc::UnboundedReceiver<(u32, ChannelData)>,
+    accepter: mpsc::UnboundedSender<(u32, ChannelData)>,
+    heartbeat: Option<Heartbeat>,
+    missed_pongs: u32,
+    /// Whether version of own frames is upgraded to the one of peer frames.
+    follow_peer: bool,
     m_receiver: mpsc::Receiver<Notice>,
     m_sender: mpsc::Sender<Notice>,
 }
@@ -45,128 +124,609 @@ where
 {
     pub fn new(sender: S, receiver: R) -> Self {
         let (tx, rx) = mpsc::channel(1024);
+        let (accepter, accepted) = mpsc::unbounded_channel();
         Self {
             sender: sender.into(),
             receiver: receiver.into(),
-            requests: std::collections::BTreeMap::<u8, CachedRequest>::default(),
+            requests: Default::default(),
+            pruned: Default::default(),
+            forgotten: 0,
+            channels: Default::default(),
+            last_channel: 0,
+            accepted,
+            accepter,
+            heartbeat: None,
+            missed_pongs: 0,
+            follow_peer: false,
             m_sender: tx,
             m_receiver: rx,
         }
     }
 
+    /// Processes incoming frames and queued requests and updates until the
+    /// stream fails. Frames of unknown type and malformed requests are
+    /// answered with `WRONG_PROTOCOL` and do not stop the loop.
+    ///
+    /// When the loop ends all pending requests are failed with
+    /// `ConnectionAborted` error, as well as requests sent after that.
+    ///
+    /// Requests are handled concurrently with the loop. Handlers are polled
+    /// only while the loop runs, and those not finished when it ends are
+    /// dropped.
+    ///
+    /// Channels accepted by request handler are registered before the frame
+    /// following the response is read, so they get the data sent after it.
+    /// Frames are not read while data of channel waits for room in its
+    /// queue, the rest of the loop goes on meanwhile.
+    ///
+    /// Pings of peer are answered all the time. Own pings are sent only
+    /// when `Heartbeat` is set, the loop fails with `NotResponding` error
+    /// if peer misses too many of them.
     pub async fn go_loop<Rq, Up>(
         &mut self,
         mut rq: Option<Rq>,
         mut up: Option<Up>,
-    ) -> io::Result<()>
+    ) -> Result<(), ProtocolError>
     where
         Rq: OnRequestH,
         Up: OnUpdateH,
     {
-        let mut result: io::Result<()> = Ok(());
+        let mut result: Result<(), ProtocolError> = Ok(());
+        let mut ticker = self.heartbeat.map(|hb| {
+            let mut ticker = time::interval_at(time::Instant::now() + hb.interval, hb.interval);
+            ticker.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+            ticker
+        });
+        self.missed_pongs = 0;
+        let mut handling = FuturesUnordered::new();
+        let mut stalled = None;
         while result.is_ok() {
+            self.accept_channels();
             select! {
-                Ok((typ, seq_id, msg)) = self.receiver.read_package() => result = match typ {
-                    FrameType::Response => self.process_response(seq_id, msg).await,
-                    FrameType::Request => self.process_request(seq_id, msg, &mut rq).await,
-                    FrameType::Notify => self.process_update(msg, &mut up).await,
+                pkg = self.receiver.read_package(), if stalled.is_none() => result = match pkg {
+                    Ok((frame, msg)) => {
+                        self.adopt_version(frame.version);
+                        match frame.typ {
+                            FrameType::Response => self.process_response(frame.seq_id, msg).await,
+                            FrameType::Request => {
+                                let (version, seq_id) = (frame.version, frame.seq_id);
+                                match self.process_request(frame, msg, &mut rq).await {
+                                    Ok(Some(rsp)) => {
+                                        handling.push(rsp.map(move |rsp| (version, seq_id, rsp)));
+                                        Ok(())
+                                    }
+                                    res => res.map(|_| ()),
+                                }
+                            }
+                            FrameType::Notify => self.process_update(msg, &mut up).await,
+                            FrameType::Channel => {
+                                self.process_channel_data(frame.seq_id, msg, &mut stalled).await
+                            }
+                            FrameType::Ping => self.process_ping(frame).await,
+                            FrameType::Pong => {
+                                self.missed_pongs = 0;
+                                Ok(())
+                            }
+                        }
+                    }
+                    Err(ProtocolError::UnknownFrame { typ, seq_id, version, .. }) => {
+                        let err = format!("Invalid value of frame type {typ}");
+                        self.reply_error(version, seq_id, Status::WRONG_PROTOCOL, err).await
+                    }
+                    Err(err) => Err(err),
                 },
                 Some(nt) = self.m_receiver.recv() => result = match nt {
                     Notice::RequestTask(req) => self.send_request(req.0, req.1).await,
                     Notice::UpdateTask(upd) => self.send_update(upd).await,
+                    Notice::Prune => {
+                        self.prune();
+                        Ok(())
+                    }
+                    Notice::OpenChannel((data, reply)) => {
+                        let _ = reply.send(self.open_channel(data));
+                        Ok(())
+                    }
+                    Notice::ChannelData((id, data)) => self.send_channel_data(id, data).await,
                 },
+                Some((version, seq_id, rsp)) = handling.next() => {
+                    result = self.reply_handled(version, seq_id, rsp).await
+                }
+                permit = capacity(&stalled) => {
+                    let (id, _, data) = stalled.take().unwrap();
+                    result = match permit {
+                        Ok(permit) => {
+                            permit.send(data);
+                            Ok(())
+                        }
+                        // Channel is dropped, but close is not sent yet
+                        Err(_) => self.send_channel_data(id, vec![]).await,
+                    }
+                }
+                _ = tick(&mut ticker) => result = self.ping().await,
             };
         }
-        result.unwrap();
-        //result
-        Ok(())
+        self.fail_pending();
+        result
+    }
+
+    /// Sets version of frames used for requests and updates. Responses are
+    /// always sent with the version of request.
+    pub fn set_frame_version(&mut self, version: FrameVersion) {
+        self.sender.set_version(version);
+    }
+
+    /// Makes requests, updates, pings and channel data to be sent with the
+    /// highest version of frames received from peer, and with `V1` until
+    /// peer sends anything, so legacy peers understand them. For the side
+    /// which does not know version of peer in advance, i.e. server.
+    pub fn follow_peer_version(&mut self) {
+        self.follow_peer = true;
+        self.sender.set_version(FrameVersion::V1);
+    }
+
+    /// Enables pinging of peer, takes effect on the next `go_loop` call.
+    pub fn set_heartbeat(&mut self, heartbeat: Option<Heartbeat>) {
+        self.heartbeat = heartbeat;
     }
 
     pub fn get_sender(&self) -> BidirectSender {
         BidirectSender {
             sender: self.m_sender.clone(),
+            accepter: self.accepter.clone(),
         }
     }
 
-    async fn process_response(&mut self, seq_id: u8, msg: Message) -> io::Result<()> {
-        let req = self.requests.remove(&seq_id);
-        if let Some(req) = req {
-            req.send(Ok(msg)).unwrap();
-            Ok(())
-        } else {
-            io::Result::Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Wrong seq_id in response",
-            ))
+    fn adopt_version(&mut self, version: FrameVersion) {
+        if self.follow_peer && version > self.sender.version() {
+            self.sender.set_version(version);
         }
     }
 
+    async fn process_response(&mut self, seq_id: u32, msg: Message) -> Result<(), ProtocolError> {
+        let Some(req) = self.requests.remove(&seq_id) else {
+            if let Some(pos) = self.pruned.iter().position(|&id| id == seq_id) {
+                self.pruned.remove(pos);
+                return Ok(());
+            }
+            // Late response of forgotten request
+            if self.forgotten > 0 {
+                self.forgotten -= 1;
+                return Ok(());
+            }
+            return Err(ProtocolError::UnexpectedResponse(seq_id));
+        };
+        // Requester may be gone already, nobody waits for the response then
+        let _ = req.send(Ok(msg));
+        Ok(())
+    }
+
+    /// Passes request to handler and returns the future of response. The
+    /// requests which could not be handled are answered right away.
     async fn process_request<Rq: OnRequestH>(
         &mut self,
-        seq_id: u8,
+        frame: Frame,
         msg: Message,
         rq: &mut Option<Rq>,
-    ) -> io::Result<()> {
-        // TODO(Shvedov) process errors
-        let rsp = if let Some(rq) = rq {
-            let req = idep::Request::parse_from_bytes(&msg)?;
-            let rsp = rq(req);
-            match rsp {
-                Ok(rsp) => rsp,
-                Err(rsp) => {
-                    let mut err = idep::Response::new();
-                    err.status = idep::response::Status::INTERNAL_ERROR.into();
-                    err.set_error(format!("{}", rsp));
-                    err
-                }
-            }
-        } else {
-            let mut err = idep::Response::new();
-            err.status = idep::response::Status::NOT_IMPLEMENTED.into();
-            err.set_error(format!("This side does not process requests."));
-            err
+    ) -> Result<Option<Rq::Future>, ProtocolError> {
+        let Some(rq) = rq else {
+            let err = "This side does not process requests.".to_string();
+            self.reply_error(frame.version, frame.seq_id, Status::NOT_IMPLEMENTED, err)
+                .await?;
+            return Ok(None);
         };
+        match idep::Request::parse_from_bytes(&msg) {
+            Ok(req) => Ok(Some(rq.on_request(req))),
+            Err(err) => {
+                let err = format!("Malformed request: {err}");
+                self.reply_error(frame.version, frame.seq_id, Status::WRONG_PROTOCOL, err)
+                    .await?;
+                Ok(None)
+            }
+        }
+    }
+
+    async fn reply_handled(
+        &mut self,
+        version: FrameVersion,
+        seq_id: u32,
+        rsp: io::Result<idep::Response>,
+    ) -> Result<(), ProtocolError> {
+        match rsp {
+            Ok(rsp) => self.reply(version, seq_id, rsp).await,
+            Err(err) => {
+                let status = match err.kind() {
+                    io::ErrorKind::Unsupported => Status::NOT_IMPLEMENTED,
+                    _ => Status::INTERNAL_ERROR,
+                };
+                self.reply_error(version, seq_id, status, err.to_string())
+                    .await
+            }
+        }
+    }
+
+    async fn reply_error(
+        &mut self,
+        version: FrameVersion,
+        seq_id: u32,
+        status: Status,
+        error: String,
+    ) -> Result<(), ProtocolError> {
+        let mut rsp = idep::Response::new();
+        rsp.status = status.into();
+        rsp.set_error(error);
+        self.reply(version, seq_id, rsp).await
+    }
+
+    async fn reply(
+        &mut self,
+        version: FrameVersion,
+        seq_id: u32,
+        rsp: idep::Response,
+    ) -> Result<(), ProtocolError> {
+        // Reply with the same version of frame, so legacy peers are supported
+        let msg = rsp.write_to_bytes()?;
         self.sender
-            .write_package(FrameType::Response, seq_id, rsp.write_to_bytes()?)
-            .await
+            .write_package_as(version, FrameType::Response, seq_id, msg)
+            .await?;
+        Ok(())
     }
 
     async fn process_update<Up: OnUpdateH>(
         &mut self,
         msg: Message,
         up: &mut Option<Up>,
-    ) -> io::Result<()> {
+    ) -> Result<(), ProtocolError> {
         let upd = idep::OnUpdate::parse_from_bytes(&msg)?;
-        up.as_mut().unwrap()(upd)
+        // Updates are not answered, so the ones nobody is interested in are dropped
+        let Some(up) = up else {
+            return Ok(());
+        };
+        up(upd).map_err(ProtocolError::Handler)
+    }
+
+    /// Passes data to consumer of channel. Data which does not fit into the
+    /// queue of channel is kept in `stalled`, and frames are not read until
+    /// the consumer takes it, so peer is slowed down by its transport.
+    async fn process_channel_data(
+        &mut self,
+        id: u32,
+        data: Message,
+        stalled: &mut Option<Stalled>,
+    ) -> Result<(), ProtocolError> {
+        if data.is_empty() {
+            self.channels.remove(&id);
+            return Ok(());
+        }
+        // Data of unknown channels is dropped, they are closed already
+        let Some(channel) = self.channels.get(&id) else {
+            return Ok(());
+        };
+        match channel.try_send(data) {
+            Ok(()) => Ok(()),
+            Err(mpsc::error::TrySendError::Full(data)) => {
+                *stalled = Some((id, channel.clone(), data));
+                Ok(())
+            }
+            // Channel is dropped, but close is not sent yet
+            Err(mpsc::error::TrySendError::Closed(_)) => self.send_channel_data(id, vec![]).await,
+        }
+    }
+
+    async fn process_ping(&mut self, frame: Frame) -> Result<(), ProtocolError> {
+        self.sender
+            .write_package_as(frame.version, FrameType::Pong, frame.seq_id, vec![])
+            .await?;
+        Ok(())
+    }
+
+    async fn ping(&mut self) -> Result<(), ProtocolError> {
+        let max_missed = self.heartbeat.map_or(0, |hb| hb.max_missed);
+        if self.missed_pongs >= max_missed {
+            return Err(ProtocolError::NotResponding);
+        }
+        self.missed_pongs += 1;
+        self.sender
+            .write_package(FrameType::Ping, 0, vec![])
+            .await?;
+        Ok(())
+    }
+
+    fn accept_channels(&mut self) {
+        while let Ok((id, data)) = self.accepted.try_recv() {
+            self.channels.insert(id, data);
+        }
+    }
+
+    fn open_channel(&mut self, data: ChannelData) -> io::Result<u32> {
+        let max_id = self.sender.version().max_seq_id();
+        if self.channels.len() >= max_id as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "Too many channels opened",
+            ));
+        }
+        loop {
+            self.last_channel = self.last_channel % max_id + 1;
+            if !self.channels.contains_key(&self.last_channel) {
+                self.channels.insert(self.last_channel, data);
+                return Ok(self.last_channel);
+            }
+        }
     }
 
-    async fn send_request(&mut self, req: idep::Request, cache: CachedRequest) -> io::Result<()> {
-        let seq_id = self.sender.write_request(req).await?;
-        self.requests.insert(seq_id, cache);
+    async fn send_channel_data(&mut self, id: u32, data: Message) -> Result<(), ProtocolError> {
+        // Peer knows channel is closed when it closed the channel itself
+        let open = match data.is_empty() {
+            true => self.channels.remove(&id).is_some(),
+            false => self.channels.contains_key(&id),
+        };
+        if open {
+            self.sender
+                .write_package(FrameType::Channel, id, data)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Forgets requests whose requesters stopped waiting, e.g. because of
+    /// timeout. Their ids are not reused until the late responses arrive, or
+    /// until there are more than `MAX_PRUNED` of them. Ids of forgotten
+    /// requests are reused, so their late responses may be taken for the
+    /// responses of new requests.
+    fn prune(&mut self) {
+        let pruned = &mut self.pruned;
+        self.requests.retain(|seq_id, cache| {
+            if cache.is_closed() {
+                pruned.push_back(*seq_id);
+            }
+            !cache.is_closed()
+        });
+        while self.pruned.len() > MAX_PRUNED {
+            self.pruned.pop_front();
+            self.forgotten += 1;
+        }
+    }
+
+    /// Fails requests waiting for response or not sent yet, and closes the
+    /// queue, so later requests fail immediately.
+    fn fail_pending(&mut self) {
+        self.pruned.clear();
+        self.forgotten = 0;
+        self.accepted.close();
+        self.accept_channels();
+        self.channels.clear();
+        let closed = || io::Error::from(ProtocolError::Closed);
+        for (_, req) in std::mem::take(&mut self.requests) {
+            let _ = req.send(Err(closed()));
+        }
+        self.m_receiver.close();
+        while let Ok(nt) = self.m_receiver.try_recv() {
+            match nt {
+                Notice::RequestTask((_, req)) => {
+                    let _ = req.send(Err(closed()));
+                }
+                Notice::OpenChannel((_, reply)) => {
+                    let _ = reply.send(Err(closed()));
+                }
+                _ => (),
+            }
+        }
+    }
+
+    async fn send_request(
+        &mut self,
+        req: idep::Request,
+        cache: CachedRequest,
+    ) -> Result<(), ProtocolError> {
+        let Some(seq_id) = self.next_seq_id() else {
+            let _ = cache.send(Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "Too many requests in flight",
+            )));
+            return Ok(());
+        };
+        match self.sender.write_request(seq_id, req).await {
+            Ok(()) => {
+                self.requests.insert(seq_id, cache);
+                Ok(())
+            }
+            // Nothing is written for too large requests, so stream is still usable
+            Err(err) if err.kind() == io::ErrorKind::InvalidInput => {
+                let _ = cache.send(Err(err));
+                Ok(())
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Returns next sequence id which does not collide with ids of requests
+    /// still waiting for response.
+    fn next_seq_id(&mut self) -> Option<u32> {
+        let in_flight = self.requests.len() + self.pruned.len();
+        if in_flight >= self.sender.version().max_seq_id() as usize {
+            return None;
+        }
+        loop {
+            let seq_id = self.sender.next_seq_id();
+            if !self.requests.contains_key(&seq_id) && !self.pruned.contains(&seq_id) {
+                return Some(seq_id);
+            }
+        }
+    }
+
+    async fn send_update(&mut self, upd: idep::OnUpdate) -> Result<(), ProtocolError> {
+        self.sender.write_update(upd).await?;
         Ok(())
     }
+}
 
-    async fn send_update(&mut self, upd: idep::OnUpdate) -> io::Result<()> {
-        self.sender.write_update(upd).await.map(|_| ())
+/// Waits until the channel of `stalled` data has room for it, forever if
+/// there is no such data.
+async fn capacity(
+    stalled: &Option<Stalled>,
+) -> Result<mpsc::OwnedPermit<Message>, mpsc::error::SendError<()>> {
+    match stalled {
+        Some((_, channel, _)) => channel.clone().reserve_owned().await,
+        None => std::future::pending().await,
     }
 }
 
+/// Waits for the next tick of heartbeat, forever if it is disabled.
+async fn tick(ticker: &mut Option<time::Interval>) {
+    match ticker {
+        Some(ticker) => {
+            ticker.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+#[derive(Clone)]
 pub struct BidirectSender {
     sender: mpsc::Sender<Notice>,
+    accepter: mpsc::UnboundedSender<(u32, ChannelData)>,
 }
 
 impl BidirectSender {
+    /// Fails with `ConnectionAborted` error when the loop of stream is not
+    /// running anymore.
     pub async fn send_request(&mut self, req: idep::Request) -> io::Result<Message> {
+        let rx = self.queue_request(req).await?;
+        rx.await.map_err(|_| ProtocolError::Closed)?
+    }
+
+    /// Same as `send_request`, but fails with `TimedOut` error if response
+    /// does not arrive in `timeout`. The late response is dropped then.
+    pub async fn send_request_timeout(
+        &mut self,
+        req: idep::Request,
+        timeout: Duration,
+    ) -> io::Result<Message> {
+        let rx = self.queue_request(req).await?;
+        match time::timeout(timeout, rx).await {
+            Ok(rsp) => rsp.map_err(|_| ProtocolError::Closed)?,
+            Err(_) => {
+                // Receiver is dropped already, so the request is pruned
+                let _ = self.sender.send(Notice::Prune).await;
+                Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "Peer is not responding",
+                ))
+            }
+        }
+    }
+
+    async fn queue_request(
+        &mut self,
+        req: idep::Request,
+    ) -> io::Result<oneshot::Receiver<io::Result<Message>>> {
         let (tx, rx) = oneshot::channel::<io::Result<Message>>();
         self.sender
             .send(Notice::RequestTask((req, tx)))
             .await
-            .unwrap();
-        rx.await.unwrap()
+            .map_err(|_| ProtocolError::Closed)?;
+        Ok(rx)
     }
 
     pub async fn send_update(&mut self, upd: idep::OnUpdate) -> io::Result<()> {
-        self.sender.send(Notice::UpdateTask(upd)).await.unwrap();
+        self.sender
+            .send(Notice::UpdateTask(upd))
+            .await
+            .map_err(|_| ProtocolError::Closed)?;
         Ok(())
     }
+
+    /// Opens channel with the next free id, which should be passed to peer,
+    /// so it could accept the channel. Ids of both sides share the same
+    /// space, so only one side of connection should open channels.
+    pub async fn open_channel(&mut self) -> io::Result<Channel> {
+        let close = self.reserve_close()?;
+        let (data, incoming) = mpsc::channel(CHANNEL_CAPACITY);
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(Notice::OpenChannel((data, tx)))
+            .await
+            .map_err(|_| ProtocolError::Closed)?;
+        let id = rx.await.map_err(|_| ProtocolError::Closed)??;
+        Ok(Channel::new(id, incoming, self.sender.clone(), close))
+    }
+
+    /// Accepts channel opened by peer. Does not wait, so it may be called
+    /// from request handler, before the response is sent.
+    pub fn accept_channel(&self, id: u32) -> io::Result<Channel> {
+        let close = self.reserve_close()?;
+        let (data, incoming) = mpsc::channel(CHANNEL_CAPACITY);
+        self.accepter
+            .send((id, data))
+            .map_err(|_| ProtocolError::Closed)?;
+        Ok(Channel::new(id, incoming, self.sender.clone(), close))
+    }
+
+    /// Reserves room in the queue of stream for close of channel, so it is
+    /// sent after the data of channel even if channel is dropped when the
+    /// queue is full.
+    fn reserve_close(&self) -> io::Result<mpsc::OwnedPermit<Notice>> {
+        self.sender
+            .clone()
+            .try_reserve_owned()
+            .map_err(|err| match err {
+                mpsc::error::TrySendError::Full(_) => {
+                    io::Error::new(io::ErrorKind::WouldBlock, "Too many channels opened")
+                }
+                mpsc::error::TrySendError::Closed(_) => ProtocolError::Closed.into(),
+            })
+    }
+}
+
+/// Stream of bytes multiplexed with other messages of `BidirectStream`, e.g.
+/// to tunnel other protocol. Closed when dropped.
+pub struct Channel {
+    id: u32,
+    incoming: mpsc::Receiver<Message>,
+    sender: mpsc::Sender<Notice>,
+    close: Option<mpsc::OwnedPermit<Notice>>,
+}
+
+impl Channel {
+    fn new(
+        id: u32,
+        incoming: mpsc::Receiver<Message>,
+        sender: mpsc::Sender<Notice>,
+        close: mpsc::OwnedPermit<Notice>,
+    ) -> Self {
+        Self {
+            id,
+            incoming,
+            sender,
+            close: Some(close),
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub async fn send(&mut self, data: Message) -> io::Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        self.sender
+            .send(Notice::ChannelData((self.id, data)))
+            .await
+            .map_err(|_| ProtocolError::Closed)?;
+        Ok(())
+    }
+
+    /// Returns `None` when channel is closed by peer or the stream is closed.
+    pub async fn recv(&mut self) -> Option<Message> {
+        self.incoming.recv().await
+    }
+}
+
+impl Drop for Channel {
+    fn drop(&mut self) {
+        if let Some(close) = self.close.take() {
+            close.send(Notice::ChannelData((self.id, vec![])));
+        }
+    }
 }
@@ -0,0 +1,54 @@
+use super::{read_message, write_message};
+use super::{Message, Receiver, Sender};
+use std::io;
+use std::net::SocketAddr;
+use tokio::net::{self, tcp::OwnedReadHalf, tcp::OwnedWriteHalf, ToSocketAddrs};
+
+pub type TcpStream = (OwnedWriteHalf, OwnedReadHalf);
+
+impl Sender for OwnedWriteHalf {
+    async fn send(&mut self, msg: Message) -> io::Result<()> {
+        write_message(self, msg).await
+    }
+}
+
+impl Receiver for OwnedReadHalf {
+    async fn recv(&mut self) -> io::Result<Message> {
+        read_message(self).await
+    }
+}
+
+fn split(stream: net::TcpStream) -> io::Result<TcpStream> {
+    stream.set_nodelay(true)?;
+    let (r, w) = stream.into_split();
+    Ok((w, r))
+}
+
+pub async fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<TcpStream> {
+    split(net::TcpStream::connect(addr).await?)
+}
+
+pub async fn listen<A: ToSocketAddrs>(addr: A) -> io::Result<Listener> {
+    Listener::bind(addr).await
+}
+
+pub struct Listener {
+    listener: net::TcpListener,
+}
+
+impl Listener {
+    pub async fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        Ok(Self {
+            listener: net::TcpListener::bind(addr).await?,
+        })
+    }
+
+    pub async fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        let (stream, addr) = self.listener.accept().await?;
+        Ok((split(stream)?, addr))
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+}
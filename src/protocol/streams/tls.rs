@@ -0,0 +1,201 @@
+use super::{read_message, write_message};
+use super::{Message, Receiver, Sender};
+use rustls::crypto::{ring, CryptoProvider};
+use rustls::pki_types::pem::{self, PemObject};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{ClientConfig, RootCertStore, ServerConfig};
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::{ReadHalf, WriteHalf};
+use tokio::net::{self, ToSocketAddrs};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+type Stream = tokio_rustls::TlsStream<net::TcpStream>;
+pub type TlsStream = (WriteHalf<Stream>, ReadHalf<Stream>);
+pub type Certificates = Vec<CertificateDer<'static>>;
+
+impl Sender for WriteHalf<Stream> {
+    async fn send(&mut self, msg: Message) -> io::Result<()> {
+        write_message(self, msg).await
+    }
+}
+
+impl Receiver for ReadHalf<Stream> {
+    async fn recv(&mut self) -> io::Result<Message> {
+        read_message(self).await
+    }
+}
+
+fn split<S: Into<Stream>>(stream: S) -> TlsStream {
+    let (r, w) = tokio::io::split(stream.into());
+    (w, r)
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(ring::default_provider())
+}
+
+fn invalid_input<E: std::error::Error + Send + Sync + 'static>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, err)
+}
+
+fn from_pem_error(err: pem::Error) -> io::Error {
+    match err {
+        pem::Error::Io(err) => err,
+        err => io::Error::new(io::ErrorKind::InvalidData, err),
+    }
+}
+
+/// Reads all certificates from PEM file at `path`.
+pub fn load_certificates<P: AsRef<Path>>(path: P) -> io::Result<Certificates> {
+    let certs = CertificateDer::pem_file_iter(path).map_err(from_pem_error)?;
+    certs.map(|cert| cert.map_err(from_pem_error)).collect()
+}
+
+/// Parses all certificates of PEM encoded `pem`.
+pub fn parse_certificates(pem: &[u8]) -> io::Result<Certificates> {
+    let certs = CertificateDer::pem_slice_iter(pem);
+    certs.map(|cert| cert.map_err(from_pem_error)).collect()
+}
+
+/// Certificate chain and private key which one side of connection proves
+/// itself with.
+pub struct Identity {
+    certs: Certificates,
+    key: PrivateKeyDer<'static>,
+}
+
+impl Identity {
+    pub fn new(certs: Certificates, key: PrivateKeyDer<'static>) -> Self {
+        Self { certs, key }
+    }
+
+    /// Reads certificate chain and private key from PEM files.
+    pub fn from_pem_files<P: AsRef<Path>>(certs: P, key: P) -> io::Result<Self> {
+        let key = PrivateKeyDer::from_pem_file(key).map_err(from_pem_error)?;
+        Ok(Self::new(load_certificates(certs)?, key))
+    }
+
+    /// Parses PEM encoded certificate chain and private key.
+    pub fn from_pem(certs: &[u8], key: &[u8]) -> io::Result<Self> {
+        let key = PrivateKeyDer::from_pem_slice(key).map_err(from_pem_error)?;
+        Ok(Self::new(parse_certificates(certs)?, key))
+    }
+}
+
+/// Makes config of server proving itself with `identity`. When `clients` are
+/// given, only clients with certificates signed by them are accepted.
+pub fn server_config(
+    identity: Identity,
+    clients: Option<Certificates>,
+) -> io::Result<Arc<ServerConfig>> {
+    let builder = ServerConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(invalid_input)?;
+    let builder = match clients {
+        Some(clients) => {
+            let verifier = WebPkiClientVerifier::builder_with_provider(
+                Arc::new(make_roots(clients)?),
+                provider(),
+            );
+            builder.with_client_cert_verifier(verifier.build().map_err(invalid_input)?)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let config = builder.with_single_cert(identity.certs, identity.key);
+    Ok(Arc::new(config.map_err(invalid_input)?))
+}
+
+/// Makes config of client which trusts servers with certificates signed by
+/// `roots`. The `identity` is required by servers authenticating clients.
+pub fn client_config(
+    roots: Certificates,
+    identity: Option<Identity>,
+) -> io::Result<Arc<ClientConfig>> {
+    let builder = ClientConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(invalid_input)?
+        .with_root_certificates(make_roots(roots)?);
+    let config = match identity {
+        Some(identity) => builder
+            .with_client_auth_cert(identity.certs, identity.key)
+            .map_err(invalid_input)?,
+        None => builder.with_no_client_auth(),
+    };
+    Ok(Arc::new(config))
+}
+
+fn make_roots(certs: Certificates) -> io::Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in certs {
+        roots.add(cert).map_err(invalid_input)?;
+    }
+    Ok(roots)
+}
+
+/// Connects to server at `addr`, which certificate should be issued for
+/// `server_name`.
+pub async fn connect<A: ToSocketAddrs>(
+    addr: A,
+    server_name: &str,
+    config: Arc<ClientConfig>,
+) -> io::Result<TlsStream> {
+    let name = ServerName::try_from(server_name.to_string()).map_err(invalid_input)?;
+    let stream = net::TcpStream::connect(addr).await?;
+    stream.set_nodelay(true)?;
+    let stream = TlsConnector::from(config).connect(name, stream).await?;
+    Ok(split(stream))
+}
+
+pub async fn listen<A: ToSocketAddrs>(addr: A, config: Arc<ServerConfig>) -> io::Result<Listener> {
+    Listener::bind(addr, config).await
+}
+
+pub struct Listener {
+    listener: net::TcpListener,
+    acceptor: TlsAcceptor,
+}
+
+impl Listener {
+    pub async fn bind<A: ToSocketAddrs>(addr: A, config: Arc<ServerConfig>) -> io::Result<Self> {
+        Ok(Self {
+            listener: net::TcpListener::bind(addr).await?,
+            acceptor: TlsAcceptor::from(config),
+        })
+    }
+
+    /// Accepts connection, TLS handshake is left to the returned
+    /// `Handshake`. It should be finished apart from accepting, e.g. in the
+    /// task of client, as client may never send anything.
+    pub async fn accept(&self) -> io::Result<(Handshake, SocketAddr)> {
+        let (stream, addr) = self.listener.accept().await?;
+        stream.set_nodelay(true)?;
+        let handshake = Handshake {
+            stream,
+            acceptor: self.acceptor.clone(),
+        };
+        Ok((handshake, addr))
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+}
+
+/// Connection accepted by `Listener`, which TLS handshake is not done yet.
+pub struct Handshake {
+    stream: net::TcpStream,
+    acceptor: TlsAcceptor,
+}
+
+impl Handshake {
+    /// Performs TLS handshake. Fails if client is not trusted, waits for
+    /// client forever, so it is usually limited with `tokio::time::timeout`.
+    pub async fn finish(self) -> io::Result<TlsStream> {
+        let stream = self.acceptor.accept(self.stream).await?;
+        Ok(split(stream))
+    }
+}
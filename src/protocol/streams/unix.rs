@@ -0,0 +1,90 @@
+use super::{read_message, write_message};
+use super::{Message, Receiver, Sender};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::net;
+pub use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+
+pub type UnixStream = (OwnedWriteHalf, OwnedReadHalf);
+
+impl Sender for OwnedWriteHalf {
+    async fn send(&mut self, msg: Message) -> io::Result<()> {
+        write_message(self, msg).await
+    }
+}
+
+impl Receiver for OwnedReadHalf {
+    async fn recv(&mut self) -> io::Result<Message> {
+        read_message(self).await
+    }
+}
+
+fn split(stream: net::UnixStream) -> UnixStream {
+    let (r, w) = stream.into_split();
+    (w, r)
+}
+
+pub async fn connect<P: AsRef<Path>>(path: P) -> io::Result<UnixStream> {
+    Ok(split(net::UnixStream::connect(path).await?))
+}
+
+/// Creates pair of connected streams, e.g. for client and server running in
+/// the same process.
+pub fn pair() -> io::Result<(UnixStream, UnixStream)> {
+    let (left, right) = net::UnixStream::pair()?;
+    Ok((split(left), split(right)))
+}
+
+/// Returns uid of the process on the other side of `stream`.
+pub fn peer_uid(stream: &UnixStream) -> io::Result<u32> {
+    Ok(stream.1.as_ref().peer_cred()?.uid())
+}
+
+pub fn listen<P: AsRef<Path>>(path: P) -> io::Result<Listener> {
+    Listener::bind(path)
+}
+
+pub struct Listener {
+    listener: net::UnixListener,
+    path: PathBuf,
+}
+
+impl Listener {
+    pub fn bind<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = PathBuf::from(path.as_ref());
+        remove_stale_socket(&path)?;
+        Ok(Self {
+            listener: net::UnixListener::bind(&path)?,
+            path,
+        })
+    }
+
+    pub async fn accept(&self) -> io::Result<UnixStream> {
+        let (stream, _) = self.listener.accept().await?;
+        Ok(split(stream))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    match std::os::unix::net::UnixStream::connect(path) {
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("Socket '{}' is already in use", path.to_str().unwrap()),
+        )),
+        Err(_) => fs::remove_file(path),
+    }
+}
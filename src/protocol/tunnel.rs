@@ -0,0 +1,74 @@
+use super::streams::{unix, Channel};
+use super::{ClientRequester, ProtocolError};
+use futures::stream::{FuturesUnordered, StreamExt as _};
+use std::io;
+use std::path::PathBuf;
+use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+use tokio::select;
+use tokio::sync::mpsc;
+
+/// Channel opened by client with the socket of editor to connect it to.
+pub type Tunnel = (Channel, PathBuf);
+
+/// Copies bytes between `channel` and `stream` until either side is closed.
+pub async fn pipe(mut channel: Channel, stream: unix::UnixStream) -> io::Result<()> {
+    let (mut writer, mut reader) = stream;
+    let mut buf = vec![0; 4096];
+    loop {
+        select! {
+            len = reader.read(&mut buf) => match len? {
+                0 => return Ok(()),
+                len => channel.send(buf[..len].to_vec()).await?,
+            },
+            data = channel.recv() => match data {
+                Some(data) => writer.write_all(&data).await?,
+                None => return Ok(()),
+            },
+        }
+    }
+}
+
+/// Makes editor of remote project at `path` available at local `listener`,
+/// e.g. for `neovide --server`. Each accepted connection gets its own tunnel.
+/// The project should be served, see `ClientRequester::serve_project`.
+pub async fn forward(
+    requester: ClientRequester,
+    path: &str,
+    listener: unix::Listener,
+) -> io::Result<()> {
+    let mut pipes = FuturesUnordered::new();
+    loop {
+        select! {
+            stream = listener.accept() => {
+                let stream = stream?;
+                let mut requester = requester.clone();
+                pipes.push(async move {
+                    let channel = requester.open_tunnel(path).await?;
+                    pipe(channel, stream).await
+                });
+            },
+            Some(res) = pipes.next() => if let Err(err) = res {
+                eprintln!("Tunnel to '{path}' failed: {err}");
+            },
+        }
+    }
+}
+
+/// Connects tunnels opened by clients to editors. Ends with `Closed` error
+/// when no more tunnels could be opened.
+pub async fn serve(tunnels: &mut mpsc::UnboundedReceiver<Tunnel>) -> Result<(), ProtocolError> {
+    let mut pipes = FuturesUnordered::new();
+    loop {
+        select! {
+            tunnel = tunnels.recv() => {
+                let Some((channel, socket)) = tunnel else {
+                    return Err(ProtocolError::Closed);
+                };
+                pipes.push(async move { pipe(channel, unix::connect(&socket).await?).await });
+            },
+            Some(res) = pipes.next() => if let Err(err) = res {
+                eprintln!("Tunnel failed: {err}");
+            },
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use crate::protocol::state;
+use crate::Project;
+use protobuf::Message as _;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::{self, Write as _};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// What server remembers about project apart from its session file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    pub last_opened: Option<SystemTime>,
+    pub tags: Vec<String>,
+    pub socket: Option<PathBuf>,
+}
+
+/// Projects known to server, stored in file, so the projects created by
+/// clients and served ones survive restart of server.
+#[derive(Debug, Clone)]
+pub struct Registry {
+    file: PathBuf,
+    entries: BTreeMap<PathBuf, Entry>,
+}
+
+/// Returns `$XDG_STATE_HOME/ide/projects`, or the same at `~/.local/state`
+/// if the variable is not set.
+pub fn default_file() -> PathBuf {
+    state_home().join("ide/projects")
+}
+
+/// Returns file of the projects opened locally, next to `default_file`.
+pub fn default_history_file() -> PathBuf {
+    state_home().join("ide/history")
+}
+
+fn state_home() -> PathBuf {
+    match env::var("XDG_STATE_HOME") {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => {
+            let home = env::var("HOME").unwrap_or(String::from("."));
+            PathBuf::from(home).join(".local/state")
+        }
+    }
+}
+
+impl Registry {
+    /// Creates empty registry to be stored at `file`.
+    pub fn new<P: AsRef<Path>>(file: P) -> Self {
+        Self {
+            file: file.as_ref().into(),
+            entries: Default::default(),
+        }
+    }
+
+    /// Reads registry from `file`. Missing file is the empty registry.
+    pub fn load<P: AsRef<Path>>(file: P) -> io::Result<Self> {
+        let mut registry = Self::new(file);
+        let content = match fs::read(&registry.file) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(registry),
+            Err(err) => return Err(err),
+        };
+        let stored = state::Registry::parse_from_bytes(&content)?;
+        for entry in stored.projects {
+            let last_opened = match entry.last_opened {
+                0 => None,
+                secs => Some(UNIX_EPOCH + Duration::from_secs(secs)),
+            };
+            registry.entries.insert(
+                PathBuf::from(entry.path),
+                Entry {
+                    name: entry.name,
+                    last_opened,
+                    tags: entry.tags,
+                    socket: entry.socket.map(PathBuf::from),
+                },
+            );
+        }
+        Ok(registry)
+    }
+
+    /// Writes registry to its file. The file is replaced atomically, so it
+    /// is never left half-written.
+    pub fn save(&self) -> io::Result<()> {
+        let mut stored = state::Registry::new();
+        for (path, entry) in &self.entries {
+            let mut stored_entry = state::registry::Entry::new();
+            stored_entry.path = path.to_str().unwrap().into();
+            stored_entry.name = entry.name.clone();
+            stored_entry.last_opened = entry.last_opened.map_or(0, |time| {
+                let since_epoch = time.duration_since(UNIX_EPOCH);
+                since_epoch.unwrap_or_default().as_secs()
+            });
+            stored_entry.tags = entry.tags.clone();
+            stored_entry.socket = entry.socket.as_ref().map(|s| s.to_str().unwrap().into());
+            stored.projects.push(stored_entry);
+        }
+        if let Some(dir) = self.file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = self.file.with_extension("tmp");
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(&stored.write_to_bytes()?)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.file)
+    }
+
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> Option<&Entry> {
+        self.entries.get(path.as_ref())
+    }
+
+    /// Sets tags of known project, returns whether the project is known.
+    pub fn set_tags<P: AsRef<Path>>(&mut self, path: P, tags: Vec<String>) -> bool {
+        let entry = self.entries.get_mut(path.as_ref());
+        entry.map(|entry| entry.tags = tags).is_some()
+    }
+
+    /// Joins `projects`, e.g. found by `Config::get_projects`, with the
+    /// registered ones. Sockets are restored only if editors still listen on
+    /// them, e.g. when server was killed without stopping its editors.
+    pub fn merge<I: IntoIterator<Item = Project>>(&self, projects: I) -> Vec<Project> {
+        let mut projects: Vec<Project> = projects.into_iter().collect();
+        for (path, entry) in &self.entries {
+            let socket = entry.socket.clone().filter(|socket| is_listening(socket));
+            match projects.iter_mut().find(|proj| &proj.path == path) {
+                Some(project) => {
+                    project.socket = socket;
+                    self.stamp(project);
+                }
+                None => projects.push(Project {
+                    name: entry.name.clone(),
+                    path: path.clone(),
+                    session_file: None,
+                    exists: path.exists(),
+                    socket,
+                    last_modified: entry.last_opened,
+                }),
+            }
+        }
+        projects
+    }
+
+    /// Takes the last opening of `project` into account, if it is known.
+    pub fn stamp(&self, project: &mut Project) {
+        let last_opened = self.get(&project.path).and_then(|entry| entry.last_opened);
+        if let Some(time) = last_opened {
+            project.opened_at(time);
+        }
+    }
+
+    /// Remembers that `project` is opened now.
+    pub fn mark_opened(&mut self, project: &Project) {
+        let entry = self.entries.entry(project.path.clone()).or_default();
+        entry.name = project.name.clone();
+        entry.last_opened = Some(SystemTime::now());
+    }
+
+    /// Makes registry to match `projects`. Projects which became served are
+    /// marked as opened now.
+    pub fn update(&mut self, projects: &[Project]) {
+        self.entries
+            .retain(|path, _| projects.iter().any(|proj| &proj.path == path));
+        for project in projects {
+            let entry = self.entries.entry(project.path.clone()).or_default();
+            if project.socket.is_some() && project.socket != entry.socket {
+                entry.last_opened = Some(SystemTime::now());
+            }
+            entry.name = project.name.clone();
+            entry.socket = project.socket.clone();
+        }
+    }
+}
+
+fn is_listening(socket: &Path) -> bool {
+    std::os::unix::net::UnixStream::connect(socket).is_ok()
+}
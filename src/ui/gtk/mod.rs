@@ -1,5 +1,6 @@
 mod grid_cell;
 
+use crate::watcher::{Event, Watcher};
 use crate::Project;
 
 use grid_cell::Entry;
@@ -13,6 +14,8 @@ use gtk::prelude::*;
 use gtk::{glib, Application};
 
 use std::cell::{Ref, RefCell};
+use std::io;
+use std::os::fd::AsRawFd;
 use std::rc::Rc;
 
 use paste;
@@ -30,6 +33,7 @@ impl UiFactory for GtkFactory {
             data: Rc::new(RefCell::new(GtkData {
                 projects: projects.map(|proj| Rc::new(proj)).collect(),
                 window: None,
+                watcher: None,
             })),
             result: None,
         })
@@ -44,6 +48,10 @@ impl super::Ui for Gtk {
         self.main();
         self.result.clone()
     }
+
+    fn watch(&mut self, watcher: Watcher) {
+        self.data.borrow_mut().watcher = Some(watcher);
+    }
 }
 
 pub struct Gtk {
@@ -54,6 +62,7 @@ pub struct Gtk {
 struct GtkData {
     projects: RcProjects,
     window: Option<GtkWindow>,
+    watcher: Option<Watcher>,
 }
 
 struct GtkWindow {
@@ -384,6 +393,36 @@ impl GtkWindow {
         let filter = self.make_filter();
         self.filter_model.set_filter(Some(&filter));
     }
+
+    fn find(&self, project: &Project) -> Option<u32> {
+        (0..self.store.n_items()).find(|&index| {
+            let item = self.store.item(index).and_downcast::<BoxedAnyObject>();
+            item.is_some_and(|item| item.borrow::<Rc<Project>>().path == project.path)
+        })
+    }
+
+    /// New sessions go on top as the most recent ones, modified are replaced
+    /// in place, so the selection is not lost.
+    fn on_session_event(&mut self, event: Event) {
+        match event {
+            Event::Added(project) => {
+                if self.find(&project).is_none() {
+                    self.store.insert(0, &BoxedAnyObject::new(Rc::new(project)));
+                }
+            }
+            Event::Modified(project) => {
+                if let Some(index) = self.find(&project) {
+                    let item = BoxedAnyObject::new(Rc::new(project));
+                    self.store.splice(index, 1, &[item]);
+                }
+            }
+            Event::Removed(project) => {
+                if let Some(index) = self.find(&project) {
+                    self.store.remove(index);
+                }
+            }
+        }
+    }
 }
 
 impl Gtk {
@@ -416,5 +455,33 @@ impl Gtk {
         let window = GtkWindow::new(application, data.clone());
         window.present();
         data.borrow_mut().window = Some(window);
+        Gtk::watch_sessions(data);
+    }
+
+    fn watch_sessions(data: RcData) {
+        let Some(mut watcher) = data.borrow_mut().watcher.take() else {
+            return;
+        };
+        let fd = watcher.as_raw_fd();
+        glib::source::unix_fd_add_local(fd, glib::IOCondition::IN, move |_, _| loop {
+            let events = match watcher.read() {
+                Ok(events) => events,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    return glib::ControlFlow::Continue;
+                }
+                Err(err) => {
+                    eprintln!("Stopped watching sessions: {err}");
+                    return glib::ControlFlow::Break;
+                }
+            };
+            // Window is gone when application quits
+            let mut data = data.borrow_mut();
+            let Some(window) = data.window.as_mut() else {
+                return glib::ControlFlow::Break;
+            };
+            for event in events {
+                window.on_session_event(event);
+            }
+        });
     }
 }
@@ -1,3 +1,4 @@
+use crate::watcher::Watcher;
 use crate::Project;
 use std::iter::Iterator;
 
@@ -7,6 +8,10 @@ mod stdio;
 
 pub trait Ui {
     fn run(&mut self) -> Option<Project>;
+    /// Lets UI to refresh the projects while running, when sessions change.
+    fn watch(&mut self, _watcher: Watcher)
+    {
+    }
 }
 
 type Projects<'a> = &'a mut dyn Iterator<Item = Project>;
@@ -23,11 +28,12 @@ pub fn new() -> Box<dyn UiFactory> {
     Box::new(gtk::GtkFactory {})
 }
 
+/// Returns UI by its case-insensitive `name`.
 pub fn from(name: &str) -> Option<Box<dyn UiFactory>> {
-    match name {
-        "Gtk" => Some(Box::new(gtk::GtkFactory {})),
-        "Stdout" => Some(Box::new(stdout::StdoutFactory {})),
-        "Stdio" => Some(Box::new(stdio::StdioFactory {})),
+    match name.to_lowercase().as_str() {
+        "gtk" => Some(Box::new(gtk::GtkFactory {})),
+        "stdout" => Some(Box::new(stdout::StdoutFactory {})),
+        "stdio" => Some(Box::new(stdio::StdioFactory {})),
         _ => None,
     }
 }
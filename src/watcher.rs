@@ -0,0 +1,101 @@
+use crate::Project;
+use std::collections::BTreeSet;
+use std::ffi::{CString, OsStr};
+use std::fs;
+use std::io;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+/// Session is written completely or moved into the folder, or removed from
+/// it.
+const MASK: u32 = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_DELETE | libc::IN_MOVED_FROM;
+
+/// Enough for many events with names up to `NAME_MAX`.
+const BUF_LEN: usize = 16 * 1024;
+
+/// Change of session file, carries the project of the session.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Added(Project),
+    Modified(Project),
+    Removed(Project),
+}
+
+/// Watches sessions folder with inotify. The descriptor is non-blocking, so
+/// watcher could be polled by event loop, e.g. with `tokio::io::unix::AsyncFd`.
+pub struct Watcher {
+    fd: OwnedFd,
+    folder: PathBuf,
+    sessions: BTreeSet<PathBuf>,
+}
+
+impl Watcher {
+    pub fn new<P: AsRef<Path>>(folder: P) -> io::Result<Self> {
+        let folder = folder.as_ref().to_path_buf();
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let path = CString::new(folder.as_os_str().as_bytes())?;
+        if unsafe { libc::inotify_add_watch(fd.as_raw_fd(), path.as_ptr(), MASK) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // Listed after the watch is added, so no session is missed
+        let mut sessions = BTreeSet::new();
+        for entry in fs::read_dir(&folder)? {
+            sessions.insert(entry?.path());
+        }
+        Ok(Self {
+            fd,
+            folder,
+            sessions,
+        })
+    }
+
+    /// Returns events happened since the last call. Fails with `WouldBlock`
+    /// if there are none.
+    pub fn read(&mut self) -> io::Result<Vec<Event>> {
+        let mut buf = vec![0u8; BUF_LEN];
+        let len = unsafe { libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let header_len = mem::size_of::<libc::inotify_event>();
+        let mut buf = &buf[..len as usize];
+        let mut events = Vec::new();
+        while buf.len() >= header_len {
+            let header: libc::inotify_event =
+                unsafe { buf.as_ptr().cast::<libc::inotify_event>().read_unaligned() };
+            let name = &buf[header_len..header_len + header.len as usize];
+            buf = &buf[header_len + header.len as usize..];
+            // Name is padded with zeros, events of the folder itself have none
+            let name = name.split(|&b| b == 0).next().unwrap_or_default();
+            if name.is_empty() {
+                continue;
+            }
+            let session_file = self.folder.join(OsStr::from_bytes(name));
+            events.extend(self.on_event(header.mask, session_file));
+        }
+        Ok(events)
+    }
+
+    fn on_event(&mut self, mask: u32, session_file: PathBuf) -> Option<Event> {
+        if mask & (libc::IN_DELETE | libc::IN_MOVED_FROM) != 0 {
+            let known = self.sessions.remove(&session_file);
+            known.then(|| Event::Removed(Project::from_session_file(session_file)))
+        } else if self.sessions.insert(session_file.clone()) {
+            Some(Event::Added(Project::from_session_file(session_file)))
+        } else {
+            Some(Event::Modified(Project::from_session_file(session_file)))
+        }
+    }
+}
+
+impl AsRawFd for Watcher {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
@@ -0,0 +1,60 @@
+use futures::join;
+use ide::protocol::*;
+use ide::Config;
+use std::io;
+use std::os::unix::fs::PermissionsExt as _;
+use tokio::sync::oneshot;
+
+mod common;
+use common::make_test_dir;
+
+#[tokio::test]
+async fn attach_to_local_server() {
+    let dir = make_test_dir("attach_to_local_server");
+    let project = ide::Project::from_path(dir.to_str().unwrap());
+    let headless = headless::Headless {
+        program: String::from("sh"),
+        args: vec![
+            String::from("-c"),
+            String::from(r#"touch "$2" && exec sleep 10"#),
+            String::from("fake-nvim"),
+        ],
+        sockets_folder: dir.join("sockets"),
+        start_timeout: std::time::Duration::from_secs(5),
+    };
+    let mut cfg = Config::new();
+    cfg.set_server_socket(dir.join("idep.sock").to_str().unwrap());
+    // Fails unless it is given socket of the served project
+    let editor = dir.join("editor.sh");
+    std::fs::write(
+        &editor,
+        "#!/bin/sh\n[ \"$1\" = --server ] && test -e \"$2\"\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&editor, std::fs::Permissions::from_mode(0o755)).unwrap();
+    cfg.set_editor(editor.to_str().unwrap());
+    cfg.set_headless(headless.clone());
+
+    let listener = streams::unix::listen(cfg.server_socket()).unwrap();
+    let context = Context::new(vec![project.clone()], headless).into_shared();
+    let (stop, shutdown) = oneshot::channel::<()>();
+    let serve = ide::daemon::serve_until(listener, context.clone(), async {
+        shutdown.await.unwrap();
+    });
+    let attach = async {
+        ide::attach::attach(&cfg, &project.name, None)
+            .await
+            .unwrap();
+        assert!(context.lock().unwrap().projects()[0].is_serving());
+
+        let err = ide::attach::attach(&cfg, "/no/such/project", None).await;
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::NotFound);
+        let err = ide::attach::attach(&cfg, &project.name, Some("remote")).await;
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::NotFound);
+        stop.send(()).unwrap();
+    };
+
+    let (res, _) = join!(serve, attach);
+    assert!(res.is_ok());
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,122 @@
+use clap::Parser as _;
+use ide::cli::Cli;
+use ide::config::{Editor, File, Source};
+use ide::Config;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+mod common;
+use common::make_test_dir;
+
+/// Makes config with sessions folder and editor of config file, which
+/// command line may override. Sessions are made in `dir`/sessions.
+fn make_config(dir: &Path) -> Config {
+    let file = File {
+        sessions_folder: Some(dir.join("file-sessions")),
+        editor: Editor::parse("neovide --no-fork"),
+        ..Default::default()
+    };
+    let mut cfg = Config::with_file(file, &dir.join("config.toml"));
+    cfg.set_history_file(dir.join("history").to_str().unwrap());
+    cfg
+}
+
+/// Writes session of project at `path`, modified `age` ago.
+fn make_session(dir: &Path, path: &str, age: Duration) -> PathBuf {
+    let session = dir.join("sessions").join(path.replace('/', "__"));
+    let file = std::fs::File::create(&session).unwrap();
+    file.set_modified(SystemTime::now() - age).unwrap();
+    session
+}
+
+fn run(dir: &Path, args: &[&str]) -> String {
+    let sessions = dir.join("sessions");
+    let mut argv = vec!["ide", "--sessions-dir", sessions.to_str().unwrap()];
+    argv.extend_from_slice(args);
+    let cli = Cli::try_parse_from(argv).unwrap();
+    let mut cfg = make_config(dir);
+    cli.configure(&mut cfg);
+    let mut out = Vec::new();
+    cli.run(cfg, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn cli_list_json() {
+    let dir = make_test_dir("cli_list_json");
+    std::fs::create_dir(dir.join("sessions")).unwrap();
+    make_session(&dir, "/a/a/a", Duration::from_secs(300));
+    make_session(&dir, "/b/b/b", Duration::from_secs(100));
+    make_session(&dir, "/c/c/c", Duration::from_secs(200));
+
+    let out = run(&dir, &["list", "--json"]);
+    let projects: serde_json::Value = serde_json::from_str(&out).unwrap();
+    let projects = projects.as_array().unwrap();
+    let names: Vec<_> = projects.iter().map(|proj| &proj["name"]).collect();
+    assert_eq!(names, ["b", "c", "a"]);
+    assert_eq!(projects[0]["path"], "/b/b/b");
+    assert_eq!(projects[0]["exists"], false);
+    assert!(projects[0]["last_modified"].is_u64());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn cli_rm() {
+    let dir = make_test_dir("cli_rm");
+    std::fs::create_dir(dir.join("sessions")).unwrap();
+    let session = make_session(&dir, "/a/a/a", Duration::ZERO);
+    make_session(&dir, "/b/b/b", Duration::ZERO);
+
+    let out = run(&dir, &["rm", "a"]);
+    assert_eq!(out, "Removed session of /a/a/a\n");
+    assert!(!session.exists());
+    assert_eq!(run(&dir, &["list"]).lines().count(), 1);
+
+    let cli = Cli::try_parse_from(["ide", "rm", "a"]).unwrap();
+    let mut cfg = make_config(&dir);
+    cfg.set_folder(dir.join("sessions").to_str().unwrap());
+    let err = cli.run(cfg, &mut Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn cli_completions() {
+    let cli = Cli::try_parse_from(["ide", "completions", "bash"]).unwrap();
+    let dir = make_test_dir("cli_completions");
+    let mut out = Vec::new();
+    cli.run(make_config(&dir), &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("_ide()"));
+    assert!(out.contains("completions"));
+
+    assert!(Cli::try_parse_from(["ide", "completions", "no-shell"]).is_err());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn cli_overrides_config() {
+    let dir = make_test_dir("cli_overrides_config");
+    let sessions = dir.join("sessions");
+    let sessions = sessions.to_str().unwrap();
+    let args = [
+        "ide",
+        "--sessions-dir",
+        sessions,
+        "--editor",
+        "nvim --clean",
+        "list",
+    ];
+    let cli = Cli::try_parse_from(args).unwrap();
+    let mut cfg = make_config(&dir);
+    cli.configure(&mut cfg);
+    let editor = cfg.editor();
+    assert_eq!(editor.value, Editor::parse("nvim --clean").unwrap());
+    assert_eq!(editor.source, Source::Cli);
+    let folder = format!("sessions_folder = {sessions:?}  # command line");
+    assert!(cfg.show().contains(&folder));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
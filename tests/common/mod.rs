@@ -0,0 +1,35 @@
+//! Helpers shared by integration tests, each of them uses only some.
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+
+/// Makes empty directory for test `name`. The path is canonical, so it
+/// matches paths of projects canonicalized by server.
+pub fn make_test_dir(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("ide-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    path.canonicalize().unwrap()
+}
+
+/// Projects which do not exist on disk, for requests not touching it.
+pub fn make_test_projects() -> Vec<ide::Project> {
+    vec![
+        ide::Project {
+            name: String::from("a"),
+            path: "/a/a/a".into(),
+            session_file: None,
+            exists: true,
+            socket: None,
+            last_modified: None,
+        },
+        ide::Project {
+            name: String::from("b"),
+            path: "/b/b/b".into(),
+            session_file: None,
+            exists: true,
+            socket: None,
+            last_modified: None,
+        },
+    ]
+}
@@ -0,0 +1,103 @@
+use ide::config::{Editor, File, Source};
+use ide::Config;
+use std::io;
+use std::path::{Path, PathBuf};
+
+mod common;
+use common::make_test_dir;
+
+const CONFIG: &str = r#"
+sessions_folder = "~/sessions"
+ui = "Stdout"
+server_socket = "/tmp/file.sock"
+token_file = "~/token"
+
+[editor]
+command = "neovide"
+args = ["--no-fork"]
+
+[listen]
+address = "0.0.0.0:7777"
+cert_file = "~/server.pem"
+key_file = "/etc/ide/server.key"
+
+[[servers]]
+name = "workstation"
+address = "workstation.local:7777"
+token_file = "~/workstation.token"
+ca_file = "~/workstation.pem"
+"#;
+
+#[test]
+fn config_file_load() {
+    let dir = make_test_dir("config_file_load");
+    let path = dir.join("config.toml");
+    std::fs::write(&path, CONFIG).unwrap();
+    let file = File::load(&path).unwrap();
+    let home = PathBuf::from(std::env::var("HOME").unwrap());
+    assert_eq!(file.sessions_folder, Some(home.join("sessions")));
+    assert_eq!(file.ui.as_deref(), Some("Stdout"));
+    assert_eq!(file.token_file, Some(home.join("token")));
+    assert_eq!(file.editor, Editor::parse("neovide --no-fork"));
+    assert_eq!(file.servers.len(), 1);
+    assert_eq!(file.servers[0].address, "workstation.local:7777");
+    assert_eq!(file.servers[0].host(), "workstation.local");
+    assert_eq!(file.servers[0].token_file, home.join("workstation.token"));
+    assert_eq!(file.servers[0].ca_file, home.join("workstation.pem"));
+    let listen = file.listen.unwrap();
+    assert_eq!(listen.cert_file, home.join("server.pem"));
+    assert_eq!(listen.key_file, PathBuf::from("/etc/ide/server.key"));
+
+    let file = File::load(dir.join("missing.toml")).unwrap();
+    assert!(file.sessions_folder.is_none() && file.servers.is_empty());
+
+    std::fs::write(&path, "sessions_dir = 1").unwrap();
+    let err = File::load(&path).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// The only test touching environment, as it is shared by all tests.
+#[test]
+fn config_precedence() {
+    let path = Path::new("/etc/ide.toml");
+    let file = || toml::from_str::<File>(CONFIG).unwrap();
+    std::env::set_var("IDE_EDITOR", "nvim --clean");
+    std::env::set_var("IDE_SOCKET", "/tmp/env.sock");
+    std::env::remove_var("IDE_SESSIONS_DIR");
+    std::env::remove_var("UI");
+
+    let mut cfg = Config::with_file(file(), path);
+    let editor = cfg.editor();
+    assert_eq!(editor.value, Editor::parse("nvim --clean").unwrap());
+    assert_eq!(editor.source, Source::Env("IDE_EDITOR"));
+    assert_eq!(cfg.server_socket(), Path::new("/tmp/env.sock"));
+    assert_eq!(cfg.servers()[0].name, "workstation");
+    let shown = cfg.show();
+    assert!(shown.contains(r#"ui = "Stdout"  # file /etc/ide.toml"#));
+    assert!(shown.contains(r#"server_socket = "/tmp/env.sock"  # env IDE_SOCKET"#));
+    assert!(shown.contains(r#"token_file = "~/token"  # file /etc/ide.toml"#));
+    assert!(shown.contains(r#"name = "workstation""#));
+    assert!(shown.contains(r#"address = "0.0.0.0:7777""#));
+
+    cfg.set_editor("vi");
+    cfg.set_server_socket("/tmp/cli.sock");
+    cfg.set_token_file("/tmp/cli.token");
+    assert_eq!(cfg.editor().source, Source::Cli);
+    let shown = cfg.show();
+    assert!(shown.contains(r#"server_socket = "/tmp/cli.sock"  # command line"#));
+    assert!(shown.contains(r#"token_file = "/tmp/cli.token"  # command line"#));
+    assert!(cfg.set_ui("Nonexistent").is_err());
+
+    std::env::remove_var("IDE_EDITOR");
+    std::env::remove_var("IDE_SOCKET");
+    let cfg = Config::with_file(Default::default(), path);
+    assert_ne!(cfg.editor().source, Source::File(path.into()));
+    assert!(cfg.show().contains("# default"));
+
+    // Typo in config falls back to the default UI
+    let mut file = file();
+    file.ui = Some(String::from("Gkt"));
+    let cfg = Config::with_file(file, path);
+    assert!(cfg.show().contains(r#"ui = "Gtk"  # default"#));
+}
@@ -0,0 +1,41 @@
+use futures::join;
+use ide::daemon;
+use ide::protocol::*;
+use tokio::sync::oneshot;
+
+mod common;
+use common::make_test_projects;
+
+#[tokio::test]
+async fn daemon_serves_many_clients() {
+    let path = std::env::temp_dir().join(format!("ide-daemon-{}.sock", std::process::id()));
+    let listener = streams::unix::listen(&path).unwrap();
+    let prjcts = make_test_projects();
+    let (stop, shutdown) = oneshot::channel::<()>();
+
+    let mut context = Context::new(prjcts.clone(), Default::default());
+    context.add_authenticator(auth::PeerUidAuthenticator::current_user());
+    let serve = daemon::serve_until(listener, context.into_shared(), async {
+        shutdown.await.unwrap();
+    });
+    let clients = async {
+        let (w, r) = streams::unix::connect(&path).await.unwrap();
+        let mut first = Client::connect(w, r, "first").await.unwrap();
+        let (w, r) = streams::unix::connect(&path).await.unwrap();
+        let mut second = Client::connect(w, r, "second").await.unwrap();
+        let (mut first_rq, mut second_rq) = (first.get_requester(), second.get_requester());
+        tokio::select! {
+            (first, second) = async { join!(first_rq.list_projects(), second_rq.list_projects()) } => {
+                assert_eq!(first.unwrap(), prjcts);
+                assert_eq!(second.unwrap(), prjcts);
+            },
+            _ = first.go_loop() => panic!("Client should never stop looping!"),
+            _ = second.go_loop() => panic!("Client should never stop looping!"),
+        }
+        stop.send(()).unwrap();
+    };
+
+    let (res, _) = join!(serve, clients);
+    assert!(res.is_ok());
+    assert!(!path.exists());
+}
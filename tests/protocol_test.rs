@@ -2,8 +2,12 @@ use futures::{join, select};
 use ide::protocol::*;
 use protobuf::Message as _;
 use std::collections::VecDeque;
+use std::time::Duration;
 use std::{cell::RefCell, io, rc::Rc};
 
+mod common;
+use common::{make_test_dir, make_test_projects};
+
 fn mk_test_error<T>() -> io::Result<T> {
     io::Result::Err(io::Error::new(io::ErrorKind::Unsupported, "TEST"))
 }
@@ -78,7 +82,7 @@ where
 {
     s: S,
     cache: VecDeque<u8>,
-    pub secs: Rc<RefCell<Vec<u8>>>,
+    pub secs: Rc<RefCell<Vec<u32>>>,
 }
 
 impl<S> Sender for TestSenderHandy<S>
@@ -87,15 +91,17 @@ where
 {
     async fn send(&mut self, msg: Message) -> io::Result<()> {
         self.cache.append(&mut msg.into());
-        if self.cache.len() < Frame::len() {
+        if self.cache.is_empty()
+            || self.cache.len() < FrameVersion::detect(self.cache[0]).unwrap().header_len()
+        {
             return Ok(());
         }
-        let frame = Frame::from(&self.cache);
-        if self.cache.len() < Frame::len() + frame.len as usize {
+        let frame = Frame::parse(&self.cache).unwrap();
+        if self.cache.len() < frame.header_len() + frame.len as usize {
             return Ok(());
         }
         self.secs.borrow_mut().push(frame.seq_id);
-        self.cache.drain(0..Frame::len());
+        self.cache.drain(0..frame.header_len());
         (self.s)(frame.typ, self.cache.drain(0..frame.len as usize).collect())
     }
 }
@@ -108,7 +114,7 @@ where
         Self {
             s: w,
             cache: VecDeque::<u8>::default(),
-            secs: Rc::new(RefCell::new(Vec::<u8>::default())),
+            secs: Rc::new(RefCell::new(Vec::<u32>::default())),
         }
     }
 }
@@ -118,7 +124,7 @@ where
     R: FnMut() -> io::Result<(FrameType, Message)>,
 {
     r: R,
-    secs: Rc<RefCell<Vec<u8>>>,
+    secs: Rc<RefCell<Vec<u32>>>,
 }
 
 impl<R> Receiver for TestReceiverHandy<R>
@@ -143,7 +149,7 @@ impl<R> TestReceiverHandy<R>
 where
     R: FnMut() -> io::Result<(FrameType, Message)>,
 {
-    pub fn new(r: R, secs: Rc<RefCell<Vec<u8>>>) -> Self {
+    pub fn new(r: R, secs: Rc<RefCell<Vec<u32>>>) -> Self {
         Self { r, secs }
     }
 }
@@ -189,23 +195,6 @@ where
     Server::new(make_handy_pair(s, r), make_test_projects())
 }
 
-fn make_test_projects() -> Vec<ide::Project> {
-    vec![
-        ide::Project {
-            name: String::from("a"),
-            path: "/a/a/a".into(),
-            session_file: None,
-            exists: true,
-        },
-        ide::Project {
-            name: String::from("b"),
-            path: "/b/b/b".into(),
-            session_file: None,
-            exists: true,
-        },
-    ]
-}
-
 #[tokio::test]
 async fn client_list_projects() {
     let mut write_called = 0;
@@ -325,7 +314,7 @@ async fn bidir_stream_request() {
     let mut sender = stream.get_sender();
     let on_req = |_| {
         on_req_called += 1;
-        mk_test_error::<idep::Response>()
+        async { mk_test_error::<idep::Response>() }
     };
     let on_upd = |_| {
         on_upd_called += 1;
@@ -335,7 +324,6 @@ async fn bidir_stream_request() {
 //    let rsp = sender.send_request(request.clone());
 //    let next = stream.go_loop(Some(on_req), Some(on_upd));
 
-
     tokio::select! {
         rsp = sender.send_request(request.clone()) => {
             assert!(rsp.is_ok());
@@ -347,3 +335,1217 @@ async fn bidir_stream_request() {
         }
     }
 }
+
+fn make_socket_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("ide-{}-{}.sock", name, std::process::id()))
+}
+
+#[tokio::test]
+async fn server_client_unix_socket() {
+    let path = make_socket_path("server_client_unix_socket");
+    let listener = streams::unix::listen(&path).unwrap();
+    let prjcts = make_test_projects();
+
+    let (client, server) = join!(streams::unix::connect(&path), listener.accept());
+    let mut client = Client::from(client.unwrap());
+    let mut requester = client.get_requester();
+    let mut server = Server::new(server.unwrap(), prjcts.clone());
+
+    tokio::select! {
+        res = requester.list_projects() => assert_eq!(res.unwrap(), prjcts),
+        _ = client.go_loop() => panic!("Client should never stop looping!"),
+        _ = server.next() => panic!("Server should never stop looping!"),
+    }
+
+    drop(listener);
+    assert!(!path.exists());
+}
+
+#[tokio::test]
+async fn server_unix_socket_closed() {
+    let path = make_socket_path("server_unix_socket_closed");
+    let listener = streams::unix::listen(&path).unwrap();
+
+    let (client, server) = join!(streams::unix::connect(&path), listener.accept());
+    let mut server = Server::new(server.unwrap(), make_test_projects());
+    drop(client);
+
+    let res = server.next().await;
+    assert!(matches!(res, Err(ProtocolError::Closed)));
+}
+
+#[tokio::test]
+async fn server_client_tcp() {
+    let listener = streams::tcp::listen("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let prjcts = make_test_projects();
+
+    let (client, server) = join!(streams::tcp::connect(addr), listener.accept());
+    let mut client = Client::from(client.unwrap());
+    let mut requester = client.get_requester();
+    let (server, peer) = server.unwrap();
+    assert!(peer.ip().is_loopback());
+    let mut server = Server::new(server, prjcts.clone());
+
+    tokio::select! {
+        res = requester.list_projects() => assert_eq!(res.unwrap(), prjcts),
+        _ = client.go_loop() => panic!("Client should never stop looping!"),
+        _ = server.next() => panic!("Server should never stop looping!"),
+    }
+}
+
+#[tokio::test]
+async fn server_client_new_project() {
+    let (left, right) = streams::VirtualStreamBuilder::new_streams();
+    let dir = make_test_dir("server_client_new_project");
+    let path = dir.to_str().unwrap();
+
+    let mut client = Client::from(left);
+    let mut requester = client.get_requester();
+    let mut server = Server::new(right, make_test_projects());
+
+    tokio::select! {
+        _ = async {
+            let prj = requester.new_project(path).await.unwrap();
+            assert_eq!(prj.path, dir);
+            assert_eq!(requester.new_project(path).await.unwrap(), prj);
+
+            let prjcts = requester.list_projects().await.unwrap();
+            assert_eq!(prjcts.len(), 3);
+            assert_eq!(prjcts[2], prj);
+
+            let err = requester.new_project("/__no__/__such__/__dir__").await;
+            assert_eq!(err.unwrap_err().kind(), io::ErrorKind::NotFound);
+            assert_eq!(requester.list_projects().await.unwrap().len(), 3);
+        } => (),
+        _ = client.go_loop() => panic!("Client should never stop looping!"),
+        _ = server.next() => panic!("Server should never stop looping!"),
+    }
+    std::fs::remove_dir(dir).unwrap();
+}
+
+#[tokio::test]
+async fn server_client_delete_project() {
+    let (left, right) = streams::VirtualStreamBuilder::new_streams();
+    let dir = make_test_dir("server_client_delete_project");
+    let session = dir.join("__a__a__a");
+    std::fs::write(&session, "").unwrap();
+    let mut prjcts = make_test_projects();
+    prjcts[0].session_file = Some(session.clone());
+
+    let mut client = Client::from(left);
+    let mut requester = client.get_requester();
+    let mut server = Server::new(right, prjcts.clone());
+
+    tokio::select! {
+        _ = async {
+            requester.delete_project("/a/a/a").await.unwrap();
+            assert!(!session.exists());
+            assert_eq!(requester.list_projects().await.unwrap(), prjcts[1..]);
+
+            let err = requester.delete_project("/a/a/a").await;
+            assert_eq!(err.unwrap_err().kind(), io::ErrorKind::NotFound);
+        } => (),
+        _ = client.go_loop() => panic!("Client should never stop looping!"),
+        _ = server.next() => panic!("Server should never stop looping!"),
+    }
+    std::fs::remove_dir(dir).unwrap();
+}
+
+fn make_fake_headless(sockets_folder: std::path::PathBuf, script: &str) -> headless::Headless {
+    headless::Headless {
+        program: String::from("sh"),
+        args: vec![
+            String::from("-c"),
+            String::from(script),
+            String::from("fake-nvim"),
+        ],
+        sockets_folder,
+        start_timeout: Duration::from_secs(5),
+    }
+}
+
+#[tokio::test]
+async fn server_client_serve_project() {
+    let (left, right) = streams::VirtualStreamBuilder::new_streams();
+    let dir = make_test_dir("server_client_serve_project");
+    let mut prjcts = make_test_projects();
+    prjcts[0].path = dir.clone();
+
+    let mut client = Client::from(left);
+    let mut requester = client.get_requester();
+    let headless = make_fake_headless(dir.join("sockets"), r#"touch "$2" && exec sleep 10"#);
+    let context = Context::new(prjcts, headless);
+    let mut server = Server::with_context(right, context.into_shared());
+
+    tokio::select! {
+        _ = async {
+            let socket = requester.serve_project(dir.to_str().unwrap()).await.unwrap();
+            assert_eq!(socket.parent().unwrap(), dir.join("sockets"));
+            assert_eq!(requester.serve_project(dir.to_str().unwrap()).await.unwrap(), socket);
+
+            let prj = requester.list_projects().await.unwrap().remove(0);
+            assert!(prj.is_serving());
+            assert!(prj.last_modified.is_some());
+            assert_eq!(prj.socket.unwrap(), socket);
+            while !socket.exists() {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+
+            let err = requester.serve_project("/no/such/project").await;
+            assert_eq!(err.unwrap_err().kind(), io::ErrorKind::NotFound);
+        } => (),
+        _ = client.go_loop() => panic!("Client should never stop looping!"),
+        _ = server.next() => panic!("Server should never stop looping!"),
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn server_broadcast_updates() {
+    use futures::StreamExt as _;
+
+    let (left, right) = streams::VirtualStreamBuilder::new_streams();
+    let (other_left, other_right) = streams::VirtualStreamBuilder::new_streams();
+    let dir = make_test_dir("server_broadcast_updates");
+    let path = dir.to_str().unwrap();
+
+    let context = Context::new(make_test_projects(), Default::default()).into_shared();
+    let mut client = Client::from(left);
+    let mut requester = client.get_requester();
+    let mut updates = client.subscribe();
+    let mut server = Server::with_context(right, context.clone());
+    let mut other = Client::from(other_left);
+    let mut other_requester = other.get_requester();
+    let mut other_updates = other.subscribe();
+    let mut other_server = Server::with_context(other_right, context);
+
+    tokio::select! {
+        _ = async {
+            requester.new_project(path).await.unwrap();
+            let upd = other_updates.next().await.unwrap();
+            assert_eq!(upd.new_project().path, path);
+
+            other_requester.delete_project(path).await.unwrap();
+            let upd = updates.next().await.unwrap();
+            assert_eq!(upd.delete_project().path, path);
+
+            requester.delete_project("/a/a/a").await.unwrap();
+            let upd = other_updates.next().await.unwrap();
+            assert_eq!(upd.delete_project().path, "/a/a/a");
+            assert!(updates.try_recv().is_err());
+        } => (),
+        _ = client.go_loop() => panic!("Client should never stop looping!"),
+        _ = server.next() => panic!("Server should never stop looping!"),
+        _ = other.go_loop() => panic!("Client should never stop looping!"),
+        _ = other_server.next() => panic!("Server should never stop looping!"),
+    }
+    std::fs::remove_dir(dir).unwrap();
+}
+
+#[tokio::test]
+async fn server_stop_project() {
+    use futures::StreamExt as _;
+
+    let (left, right) = streams::VirtualStreamBuilder::new_streams();
+    let dir = make_test_dir("server_stop_project");
+    let path = dir.to_str().unwrap();
+    let mut prjcts = make_test_projects();
+    prjcts[0].path = dir.clone();
+
+    let mut client = Client::from(left);
+    let mut requester = client.get_requester();
+    let mut updates = client.subscribe();
+    let headless = make_fake_headless(dir.join("sockets"), r#"touch "$2" && exec sleep 10"#);
+    let context = Context::new(prjcts, headless).into_shared();
+    let mut server = Server::with_context(right, context.clone());
+
+    tokio::select! {
+        _ = async {
+            let socket = requester.serve_project(path).await.unwrap();
+            while !socket.exists() {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+            requester.stop_project(path).await.unwrap();
+
+            let upd = updates.next().await.unwrap();
+            let upd = upd.project_state_changed();
+            assert_eq!(upd.path, path);
+            assert_eq!(upd.new_state.enum_value_or_default(), idep::project::State::PROJECT_IDLE);
+            assert!(!socket.exists());
+            assert!(!requester.list_projects().await.unwrap()[0].is_serving());
+
+            let err = requester.stop_project("/no/such/project").await;
+            assert_eq!(err.unwrap_err().kind(), io::ErrorKind::NotFound);
+        } => (),
+        _ = client.go_loop() => panic!("Client should never stop looping!"),
+        _ = server.next() => panic!("Server should never stop looping!"),
+        _ = Context::supervise(context.clone()) => panic!("Supervisor should never stop!"),
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn server_delete_served_project() {
+    let (left, right) = streams::VirtualStreamBuilder::new_streams();
+    let dir = make_test_dir("server_delete_served_project");
+    let path = dir.to_str().unwrap();
+    let mut prjcts = make_test_projects();
+    prjcts[0].path = dir.clone();
+
+    let mut client = Client::from(left);
+    let mut requester = client.get_requester();
+    let headless = make_fake_headless(dir.join("sockets"), r#"touch "$2" && exec sleep 10"#);
+    let context = Context::new(prjcts, headless).into_shared();
+    let mut server = Server::with_context(right, context.clone());
+
+    tokio::select! {
+        _ = async {
+            let socket = requester.serve_project(path).await.unwrap();
+            while !socket.exists() {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+            requester.delete_project(path).await.unwrap();
+            assert!(!socket.exists());
+        } => (),
+        _ = client.go_loop() => panic!("Client should never stop looping!"),
+        _ = server.next() => panic!("Server should never stop looping!"),
+        _ = Context::supervise(context.clone()) => panic!("Supervisor should never stop!"),
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn server_editor_exited() {
+    use futures::StreamExt as _;
+
+    let (left, right) = streams::VirtualStreamBuilder::new_streams();
+    let dir = make_test_dir("server_editor_exited");
+    let path = dir.to_str().unwrap();
+    let mut prjcts = make_test_projects();
+    prjcts[0].path = dir.clone();
+
+    let mut client = Client::from(left);
+    let mut requester = client.get_requester();
+    let mut updates = client.subscribe();
+    let headless = make_fake_headless(dir.join("sockets"), r#"touch "$2" && exec sleep 0.2"#);
+    let context = Context::new(prjcts, headless).into_shared();
+    let mut server = Server::with_context(right, context.clone());
+
+    tokio::select! {
+        _ = async {
+            let socket = requester.serve_project(path).await.unwrap();
+            let upd = updates.next().await.unwrap();
+            let upd = upd.project_state_changed();
+            assert_eq!(upd.path, path);
+            assert_eq!(upd.new_state.enum_value_or_default(), idep::project::State::PROJECT_IDLE);
+            assert!(!socket.exists());
+            assert!(!requester.list_projects().await.unwrap()[0].is_serving());
+        } => (),
+        _ = client.go_loop() => panic!("Client should never stop looping!"),
+        _ = server.next() => panic!("Server should never stop looping!"),
+        _ = Context::supervise(context.clone()) => panic!("Supervisor should never stop!"),
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn server_editor_not_listening() {
+    let (left, right) = streams::VirtualStreamBuilder::new_streams();
+    let dir = make_test_dir("server_editor_not_listening");
+    let path = dir.to_str().unwrap();
+    let mut prjcts = make_test_projects();
+    prjcts[0].path = dir.clone();
+
+    let mut client = Client::from(left);
+    let mut requester = client.get_requester();
+    let mut updates = client.subscribe();
+    let headless = headless::Headless {
+        start_timeout: Duration::from_millis(100),
+        ..make_fake_headless(dir.join("sockets"), "exec sleep 10")
+    };
+    let context = Context::new(prjcts, headless).into_shared();
+    let mut server = Server::with_context(right, context.clone());
+
+    tokio::select! {
+        _ = async {
+            let err = requester.serve_project(path).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::Other);
+            assert!(!requester.list_projects().await.unwrap()[0].is_serving());
+            // Exit of the stopped editor is not notified about once more
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            assert!(updates.try_recv().is_err());
+        } => (),
+        _ = client.go_loop() => panic!("Client should never stop looping!"),
+        _ = server.next() => panic!("Server should never stop looping!"),
+        _ = Context::supervise(context.clone()) => panic!("Supervisor should never stop!"),
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn headless_socket_name_is_bounded() {
+    let headless = headless::Headless::default();
+    let long = format!("/tmp/{}/project", "a".repeat(200));
+    let sockets = [long.as_str(), "/a/project", "/b/project"].map(|path| {
+        let socket = headless.socket_for(&ide::Project::from_path(path));
+        assert_eq!(socket.parent().unwrap(), headless.sockets_folder);
+        assert!(socket.file_name().unwrap().len() < 64);
+        socket
+    });
+    assert_ne!(sockets[0], sockets[1]);
+    assert_ne!(sockets[1], sockets[2]);
+}
+
+#[tokio::test]
+async fn server_legacy_frame() {
+    let (mut left, right) = streams::VirtualStreamBuilder::new_streams();
+    let mut server = Server::new(right, make_test_projects());
+
+    let mut req = idep::Request::new();
+    req.set_list_projects(idep::request::ListProjects::new());
+    let req = req.write_to_bytes().unwrap();
+    let frame = Frame::with_version(FrameVersion::V1, FrameType::Request, 7, req.len() as u16);
+    let mut receiver = streams::PackageReceiver::from(left.1);
+
+    tokio::select! {
+        _ = async {
+            left.0.send(frame.into()).await.unwrap();
+            left.0.send(req).await.unwrap();
+            let (frame, rsp) = receiver.read_package().await.unwrap();
+            assert_eq!(frame.version, FrameVersion::V1);
+            assert_eq!(frame.typ, FrameType::Response);
+            assert_eq!(frame.seq_id, 7);
+            let rsp = idep::Response::parse_from_bytes(&rsp).unwrap();
+            assert_eq!(Vec::<ide::Project>::from(rsp.list_projects()), make_test_projects());
+        } => (),
+        _ = server.next() => panic!("Server should never stop looping!"),
+    }
+}
+
+/// Reads frame the way legacy peers do, they know only `V1` headers.
+async fn read_legacy_frame<R: Receiver>(
+    receiver: &mut streams::HandyReceiver<R>,
+) -> (u8, u8, Message) {
+    let header = receiver.read_exact(4).await.unwrap();
+    assert_eq!(header[0] & 0x80, 0, "Legacy peer got versioned frame");
+    let len = u16::from_be_bytes([header[2], header[3]]);
+    let msg = receiver.read_exact(len.into()).await.unwrap();
+    (header[0], header[1], msg)
+}
+
+#[tokio::test]
+async fn server_notifies_legacy_client() {
+    let (mut left, right) = streams::VirtualStreamBuilder::new_streams();
+    let (other_left, other_right) = streams::VirtualStreamBuilder::new_streams();
+    let dir = make_test_dir("server_notifies_legacy_client");
+    let path = dir.to_str().unwrap();
+
+    let context = Context::new(make_test_projects(), Default::default()).into_shared();
+    let mut server = Server::with_context(right, context.clone());
+    let mut other = Client::from(other_left);
+    let mut requester = other.get_requester();
+    let mut other_server = Server::with_context(other_right, context);
+
+    let mut req = idep::Request::new();
+    req.set_list_projects(idep::request::ListProjects::new());
+    let req = req.write_to_bytes().unwrap();
+    let frame = Frame::with_version(FrameVersion::V1, FrameType::Request, 7, req.len() as u16);
+    let mut receiver = streams::HandyReceiver::from(left.1);
+
+    tokio::select! {
+        _ = async {
+            left.0.send(frame.into()).await.unwrap();
+            left.0.send(req).await.unwrap();
+            let (typ, seq_id, _) = read_legacy_frame(&mut receiver).await;
+            assert_eq!((typ, seq_id), (FrameType::Response as u8, 7));
+
+            requester.new_project(path).await.unwrap();
+            let (typ, _, upd) = read_legacy_frame(&mut receiver).await;
+            assert_eq!(typ, FrameType::Notify as u8);
+            let upd = idep::OnUpdate::parse_from_bytes(&upd).unwrap();
+            assert_eq!(upd.new_project().path, path);
+        } => (),
+        _ = server.next() => panic!("Server should never stop looping!"),
+        _ = other.go_loop() => panic!("Client should never stop looping!"),
+        _ = other_server.next() => panic!("Server should never stop looping!"),
+    }
+    std::fs::remove_dir(dir).unwrap();
+}
+
+#[tokio::test]
+async fn server_unknown_frame() {
+    let (mut left, right) = streams::VirtualStreamBuilder::new_streams();
+    let mut server = Server::new(right, make_test_projects());
+
+    let mut header = vec![0x82, 0x3f];
+    header.extend_from_slice(&9u32.to_be_bytes());
+    header.extend_from_slice(&3u16.to_be_bytes());
+    let mut req = idep::Request::new();
+    req.set_list_projects(idep::request::ListProjects::new());
+    let req = req.write_to_bytes().unwrap();
+    let req_frame = Frame::new(FrameType::Request, 10, req.len() as u16);
+    let mut receiver = streams::PackageReceiver::from(left.1);
+
+    tokio::select! {
+        _ = async {
+            left.0.send(header).await.unwrap();
+            left.0.send(vec![1, 2, 3]).await.unwrap();
+            let (frame, rsp) = receiver.read_package().await.unwrap();
+            assert_eq!(frame.typ, FrameType::Response);
+            assert_eq!(frame.seq_id, 9);
+            let rsp = idep::Response::parse_from_bytes(&rsp).unwrap();
+            assert_eq!(rsp.status, idep::response::Status::WRONG_PROTOCOL.into());
+
+            left.0.send(req_frame.into()).await.unwrap();
+            left.0.send(req).await.unwrap();
+            let (frame, rsp) = receiver.read_package().await.unwrap();
+            assert_eq!(frame.seq_id, 10);
+            let rsp = idep::Response::parse_from_bytes(&rsp).unwrap();
+            assert_eq!(Vec::<ide::Project>::from(rsp.list_projects()), make_test_projects());
+        } => (),
+        _ = server.next() => panic!("Server should never stop looping!"),
+    }
+}
+
+#[tokio::test]
+async fn client_pending_requests_closed() {
+    let (left, right) = streams::unix::pair().unwrap();
+    let mut client = Client::from(left);
+    let mut requester = client.get_requester();
+
+    let (writer, reader) = right;
+    let server = async move {
+        let mut receiver = streams::PackageReceiver::from(reader);
+        let (frame, _) = receiver.read_package().await.unwrap();
+        assert_eq!(frame.typ, FrameType::Request);
+        drop(writer);
+    };
+    let (res, rsp, _) = join!(client.go_loop(), requester.list_projects(), server);
+    assert!(matches!(res, Err(ProtocolError::Closed)));
+    assert_eq!(rsp.unwrap_err().kind(), io::ErrorKind::ConnectionAborted);
+
+    let rsp = requester.list_projects().await;
+    assert_eq!(rsp.unwrap_err().kind(), io::ErrorKind::ConnectionAborted);
+}
+
+#[tokio::test]
+async fn client_request_timeout() {
+    let (left, right) = streams::unix::pair().unwrap();
+    let prjcts = make_test_projects();
+    let rsp = idep::Response::from(&prjcts).write_to_bytes().unwrap();
+
+    let mut client = Client::from(left);
+    let requester = client.get_requester();
+    let mut sender = streams::PackageSender::from(right.0);
+    let mut receiver = streams::PackageReceiver::from(right.1);
+
+    tokio::select! {
+        _ = async {
+            let timeout = Duration::from_millis(100);
+            let mut impatient = requester.with_timeout(timeout);
+            let (res, late) = join!(impatient.list_projects(), receiver.read_package());
+            assert_eq!(res.unwrap_err().kind(), io::ErrorKind::TimedOut);
+            // Late response is dropped and does not break the stream
+            let (late, _) = late.unwrap();
+            sender.send_response(late.seq_id, rsp.clone()).await.unwrap();
+
+            let mut requester = requester.clone();
+            let server = async {
+                let (frame, _) = receiver.read_package().await.unwrap();
+                assert_ne!(frame.seq_id, late.seq_id);
+                sender.send_response(frame.seq_id, rsp.clone()).await.unwrap();
+            };
+            let (res, _) = join!(requester.list_projects(), server);
+            assert_eq!(res.unwrap(), prjcts);
+        } => (),
+        _ = client.go_loop() => panic!("Client should never stop looping!"),
+    }
+}
+
+#[tokio::test]
+async fn client_many_requests_in_flight() {
+    let (left, right) = streams::unix::pair().unwrap();
+    let prjcts = make_test_projects();
+
+    let mut client = Client::from(left);
+    // More requests than ids of `V1` frames, server answers with `V2` ones too
+    client.set_frame_version(FrameVersion::V2);
+    let requester = client.get_requester();
+    let mut server = Server::new(right, prjcts.clone());
+
+    let requests = (0..1000).map(|_| {
+        let mut requester = requester.clone();
+        async move { requester.list_projects().await }
+    });
+    tokio::select! {
+        res = futures::future::join_all(requests) => {
+            assert!(res.into_iter().all(|res| res.unwrap() == prjcts));
+        },
+        _ = client.go_loop() => panic!("Client should never stop looping!"),
+        _ = server.next() => panic!("Server should never stop looping!"),
+    }
+}
+
+#[tokio::test]
+async fn client_legacy_seq_id_exhausted() {
+    use futures::StreamExt as _;
+
+    let (left, _right) = streams::VirtualStreamBuilder::new_streams();
+    let mut client = Client::new(TestSender::from(|_| Ok(())), left.1);
+    client.set_frame_version(FrameVersion::V1);
+    let requester = client.get_requester();
+
+    let mut requests: futures::stream::FuturesUnordered<_> = (0..256)
+        .map(|_| {
+            let mut requester = requester.clone();
+            async move { requester.list_projects().await }
+        })
+        .collect();
+    tokio::select! {
+        res = requests.next() => {
+            assert_eq!(res.unwrap().unwrap_err().kind(), io::ErrorKind::WouldBlock);
+        },
+        _ = client.go_loop() => panic!("Client should never stop looping!"),
+    }
+}
+
+#[tokio::test]
+async fn client_legacy_hung_server() {
+    let (left, right) = streams::unix::pair().unwrap();
+    let prjcts = make_test_projects();
+    let rsp = idep::Response::from(&prjcts).write_to_bytes().unwrap();
+
+    let mut client = Client::from(left);
+    client.set_frame_version(FrameVersion::V1);
+    let requester = client.get_requester();
+    let mut sender = streams::PackageSender::from(right.0);
+    sender.set_version(FrameVersion::V1);
+    let mut receiver = streams::PackageReceiver::from(right.1);
+
+    // Server recovers after more requests than ids of `V1` frames time out
+    let timeout = Duration::from_millis(50);
+    tokio::select! {
+        _ = async {
+            let hung = async {
+                for _ in 0..3 {
+                    let requests = (0..100).map(|_| {
+                        let mut impatient = requester.with_timeout(timeout);
+                        async move { impatient.list_projects().await }
+                    });
+                    for res in futures::future::join_all(requests).await {
+                        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::TimedOut);
+                    }
+                }
+            };
+            let ignoring = async {
+                let mut first = None;
+                for _ in 0..300 {
+                    let (frame, _) = receiver.read_package().await.unwrap();
+                    first.get_or_insert(frame.seq_id);
+                }
+                first.unwrap()
+            };
+            let ((), first) = join!(hung, ignoring);
+            // Late response of long forgotten request is dropped
+            sender.send_response(first, rsp.clone()).await.unwrap();
+            let mut requester = requester.clone();
+            let server = async {
+                let (frame, _) = receiver.read_package().await.unwrap();
+                sender.send_response(frame.seq_id, rsp.clone()).await.unwrap();
+            };
+            let (res, _) = join!(requester.list_projects(), server);
+            assert_eq!(res.unwrap(), prjcts);
+        } => (),
+        _ = client.go_loop() => panic!("Client should never stop looping!"),
+    }
+}
+
+#[tokio::test]
+async fn server_client_large_message() {
+    let (left, right) = streams::unix::pair().unwrap();
+    let prjcts: Vec<ide::Project> = (0..5000)
+        .map(|i| ide::Project {
+            name: format!("project{i}"),
+            path: format!("/some/long/enough/path/to/project{i}").into(),
+            session_file: None,
+            exists: true,
+            socket: None,
+            last_modified: None,
+        })
+        .collect();
+    assert!(idep::Response::from(&prjcts).write_to_bytes().unwrap().len() > MAX_FRAME_LEN);
+
+    let mut client = Client::from(left);
+    let mut requester = client.get_requester();
+    let mut server = Server::new(right, prjcts.clone());
+
+    tokio::select! {
+        res = requester.list_projects() => assert_eq!(res.unwrap(), prjcts),
+        _ = client.go_loop() => panic!("Client should never stop looping!"),
+        _ = server.next() => panic!("Server should never stop looping!"),
+    }
+}
+
+#[tokio::test]
+async fn package_too_large() {
+    let mut sender = streams::PackageSender::from(TestSender::from(|_| Ok(())));
+    let msg = vec![0; MAX_MESSAGE_LEN + 1];
+    let res = sender.write_package(FrameType::Request, 1, msg).await;
+    assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn project_last_modified() {
+    let mut prj = make_test_projects().remove(0);
+    assert_eq!(ide::Project::from(idep::Project::from(&prj)), prj);
+
+    prj.last_modified = Some(std::time::UNIX_EPOCH + Duration::from_secs(1700000000));
+    let proto = idep::Project::from(&prj);
+    assert_eq!(proto.last_modified, "1700000000");
+    assert_eq!(ide::Project::from(proto), prj);
+}
+
+#[tokio::test]
+async fn server_client_hello() {
+    let (left, right) = streams::unix::pair().unwrap();
+    let mut server = Server::new(right, make_test_projects());
+
+    tokio::select! {
+        client = Client::connect(left.0, left.1, "test") => {
+            let client = client.unwrap();
+            let hello = client.server().unwrap();
+            assert_eq!(hello.version, PROTOCOL_VERSION);
+            assert!(hello.name.starts_with("ide-server"));
+            assert!(client.supports(Capability::STOP_PROJECT));
+        },
+        _ = server.next() => panic!("Server should never stop looping!"),
+    }
+}
+
+#[tokio::test]
+async fn server_wrong_protocol_version() {
+    let (left, right) = streams::unix::pair().unwrap();
+    let mut server = Server::new(right, make_test_projects());
+    let mut sender = streams::PackageSender::from(left.0);
+    let mut receiver = streams::PackageReceiver::from(left.1);
+
+    let mut hello = idep::Hello::new();
+    hello.version = PROTOCOL_VERSION + 1;
+    let mut req = idep::Request::new();
+    req.set_hello(hello);
+
+    tokio::select! {
+        _ = async {
+            sender.write_request(1, req).await.unwrap();
+            let (_, rsp) = receiver.read_package().await.unwrap();
+            let rsp = idep::Response::parse_from_bytes(&rsp).unwrap();
+            assert_eq!(rsp.status, idep::response::Status::WRONG_PROTOCOL.into());
+        } => (),
+        _ = server.next() => panic!("Server should never stop looping!"),
+    }
+}
+
+/// Answers `Hello` request with `rsp`, returns the stream to keep it open.
+async fn answer_hello(
+    stream: streams::unix::UnixStream,
+    rsp: idep::Response,
+) -> (
+    streams::PackageSender<streams::unix::OwnedWriteHalf>,
+    streams::PackageReceiver<streams::unix::OwnedReadHalf>,
+) {
+    let mut sender = streams::PackageSender::from(stream.0);
+    let mut receiver = streams::PackageReceiver::from(stream.1);
+    let (frame, req) = receiver.read_package().await.unwrap();
+    assert_eq!(frame.version, FrameVersion::V1);
+    assert!(idep::Request::parse_from_bytes(&req).unwrap().has_hello());
+    let rsp = rsp.write_to_bytes().unwrap();
+    sender
+        .write_package_as(frame.version, FrameType::Response, frame.seq_id, rsp)
+        .await
+        .unwrap();
+    (sender, receiver)
+}
+
+/// Returns version of frame the next request of `client` is sent with.
+async fn next_request_version<S: Sender, R: Receiver, PR: Receiver>(
+    client: &mut Client<S, R>,
+    receiver: &mut streams::PackageReceiver<PR>,
+) -> FrameVersion {
+    let mut requester = client.get_requester();
+    tokio::select! {
+        _ = requester.list_projects() => panic!("Nobody answers requests"),
+        res = receiver.read_package() => res.unwrap().0.version,
+        _ = client.go_loop() => panic!("Client should never stop looping!"),
+    }
+}
+
+#[tokio::test]
+async fn client_legacy_frames_by_default() {
+    let (left, right) = streams::unix::pair().unwrap();
+    let mut client = Client::from(left);
+    let mut receiver = streams::PackageReceiver::from(right.1);
+    let version = next_request_version(&mut client, &mut receiver).await;
+    assert_eq!(version, FrameVersion::V1);
+}
+
+#[tokio::test]
+async fn client_older_server() {
+    let (left, right) = streams::unix::pair().unwrap();
+    let mut hello = idep::Hello::new();
+    hello.version = PROTOCOL_VERSION;
+    hello.capabilities = vec![
+        Capability::LIST_PROJECTS.into(),
+        protobuf::EnumOrUnknown::from_i32(42),
+    ];
+    let mut rsp = idep::Response::new();
+    rsp.set_hello(hello);
+
+    let connect = Client::connect(left.0, left.1, "test");
+    let (client, (_sender, mut receiver)) = join!(connect, answer_hello(right, rsp));
+    let mut client = client.unwrap();
+    assert!(client.supports(Capability::LIST_PROJECTS));
+    assert!(!client.supports(Capability::STOP_PROJECT));
+    let res = client.get_requester().stop_project("/some/path").await;
+    assert_eq!(res.unwrap_err().kind(), io::ErrorKind::Unsupported);
+    // Server did not announce `VERSIONED_FRAMES`
+    let version = next_request_version(&mut client, &mut receiver).await;
+    assert_eq!(version, FrameVersion::V1);
+}
+
+#[tokio::test]
+async fn client_versioned_frames_server() {
+    let (left, right) = streams::unix::pair().unwrap();
+    let mut hello = idep::Hello::new();
+    hello.version = PROTOCOL_VERSION;
+    hello.capabilities = vec![
+        Capability::LIST_PROJECTS.into(),
+        Capability::VERSIONED_FRAMES.into(),
+    ];
+    let mut rsp = idep::Response::new();
+    rsp.set_hello(hello);
+
+    let connect = Client::connect(left.0, left.1, "test");
+    let (client, (_sender, mut receiver)) = join!(connect, answer_hello(right, rsp));
+    let mut client = client.unwrap();
+    let version = next_request_version(&mut client, &mut receiver).await;
+    assert_eq!(version, FrameVersion::V2);
+}
+
+#[tokio::test]
+async fn client_hello_failed() {
+    let (left, right) = streams::unix::pair().unwrap();
+    let mut rsp = idep::Response::new();
+    rsp.status = idep::response::Status::INTERNAL_ERROR.into();
+    rsp.set_error("Disk is full".into());
+
+    let connect = Client::connect(left.0, left.1, "test");
+    let (client, _server) = join!(connect, answer_hello(right, rsp));
+    assert_eq!(client.err().unwrap().kind(), io::ErrorKind::Other);
+}
+
+/// Serves the way legacy servers do: knows only `V1` headers and
+/// `ListProjects` request, answers others with `INTERNAL_ERROR`.
+async fn legacy_server(stream: streams::unix::UnixStream) {
+    let (mut writer, reader) = stream;
+    let mut receiver = streams::HandyReceiver::from(reader);
+    loop {
+        let (typ, seq_id, req) = read_legacy_frame(&mut receiver).await;
+        assert_eq!(typ, FrameType::Request as u8);
+        let req = idep::Request::parse_from_bytes(&req).unwrap();
+        let rsp = if req.has_list_projects() {
+            idep::Response::from(&make_test_projects())
+        } else {
+            let mut rsp = idep::Response::new();
+            rsp.status = idep::response::Status::INTERNAL_ERROR.into();
+            rsp.set_error("Not implemented yet".into());
+            rsp
+        };
+        let rsp = rsp.write_to_bytes().unwrap();
+        let len = rsp.len() as u16;
+        let frame = Frame::with_version(FrameVersion::V1, FrameType::Response, seq_id.into(), len);
+        writer.send(frame.into()).await.unwrap();
+        writer.send(rsp).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn client_legacy_server() {
+    let (left, right) = streams::unix::pair().unwrap();
+
+    tokio::select! {
+        _ = async {
+            let mut client = Client::connect(left.0, left.1, "test").await.unwrap();
+            assert!(client.server().is_none());
+            assert!(client.supports(Capability::STOP_PROJECT));
+            let mut requester = client.get_requester();
+            tokio::select! {
+                res = requester.list_projects() => assert_eq!(res.unwrap(), make_test_projects()),
+                _ = client.go_loop() => panic!("Client should never stop looping!"),
+            }
+        } => (),
+        _ = legacy_server(right) => panic!("Server should never stop looping!"),
+    }
+}
+
+#[tokio::test]
+async fn server_token_auth() {
+    let mut context = Context::new(make_test_projects(), Default::default());
+    context.add_authenticator(auth::TokenAuthenticator::new("secret"));
+    let context = context.into_shared();
+
+    let (left, right) = streams::unix::pair().unwrap();
+    let mut server = Server::with_context(right, context.clone());
+    tokio::select! {
+        client = Client::connect_with_token(left.0, left.1, "test", "wrong") => {
+            assert_eq!(client.err().unwrap().kind(), io::ErrorKind::PermissionDenied);
+        },
+        _ = server.next() => panic!("Server should never stop looping!"),
+    }
+
+    let (left, right) = streams::unix::pair().unwrap();
+    let mut server = Server::with_context(right, context.clone());
+    tokio::select! {
+        _ = async {
+            let mut client = Client::connect_with_token(left.0, left.1, "test", "secret")
+                .await
+                .unwrap();
+            let mut requester = client.get_requester();
+            tokio::select! {
+                res = requester.list_projects() => assert_eq!(res.unwrap(), make_test_projects()),
+                _ = client.go_loop() => panic!("Client should never stop looping!"),
+            }
+        } => (),
+        _ = server.next() => panic!("Server should never stop looping!"),
+    }
+}
+
+#[tokio::test]
+async fn server_pipelined_hello() {
+    let mut context = Context::new(make_test_projects(), Default::default());
+    context.add_authenticator(auth::TokenAuthenticator::new("secret"));
+    let (left, right) = streams::unix::pair().unwrap();
+    let mut server = Server::with_context(right, context.into_shared());
+    let mut sender = streams::PackageSender::from(left.0);
+    let mut receiver = streams::PackageReceiver::from(left.1);
+
+    let mut hello = idep::Hello::new();
+    hello.version = PROTOCOL_VERSION;
+    hello.token = "secret".into();
+    let mut req = idep::Request::new();
+    req.set_hello(hello);
+    let mut list = idep::Request::new();
+    list.set_list_projects(idep::request::ListProjects::new());
+
+    tokio::select! {
+        _ = async {
+            // Requests following `Hello` are sent before it is answered
+            sender.write_request(1, req).await.unwrap();
+            for seq_id in 2..10 {
+                sender.write_request(seq_id, list.clone()).await.unwrap();
+            }
+            for _ in 1..10 {
+                let (_, rsp) = receiver.read_package().await.unwrap();
+                let rsp = idep::Response::parse_from_bytes(&rsp).unwrap();
+                assert_eq!(rsp.status, idep::response::Status::OK.into());
+            }
+        } => (),
+        _ = server.next() => panic!("Server should never stop looping!"),
+    }
+}
+
+#[tokio::test]
+async fn server_rejects_unauthenticated_requests() {
+    let mut context = Context::new(make_test_projects(), Default::default());
+    context.add_authenticator(auth::TokenAuthenticator::new("secret"));
+    let (left, right) = streams::unix::pair().unwrap();
+    let mut server = Server::with_context(right, context.into_shared());
+    let mut client = Client::from(left);
+    let mut requester = client.get_requester();
+
+    tokio::select! {
+        res = requester.list_projects() => {
+            assert_eq!(res.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        },
+        _ = client.go_loop() => panic!("Client should never stop looping!"),
+        _ = server.next() => panic!("Server should never stop looping!"),
+    }
+}
+
+#[tokio::test]
+async fn server_peer_uid_auth() {
+    let uid = unsafe { libc::getuid() };
+    let mut context = Context::new(make_test_projects(), Default::default());
+    context.add_authenticator(auth::PeerUidAuthenticator::new(uid + 1));
+    let context = context.into_shared();
+
+    let (left, right) = streams::unix::pair().unwrap();
+    assert_eq!(streams::unix::peer_uid(&right).unwrap(), uid);
+    let mut server = Server::with_context(right, context);
+    server.set_peer(auth::Peer { uid: Some(uid) });
+    tokio::select! {
+        client = Client::connect(left.0, left.1, "test") => {
+            assert_eq!(client.err().unwrap().kind(), io::ErrorKind::PermissionDenied);
+        },
+        _ = server.next() => panic!("Server should never stop looping!"),
+    }
+}
+
+#[test]
+fn read_token_file() {
+    use std::os::unix::fs::PermissionsExt;
+    let path = make_test_dir("read_token_file").join("token");
+    std::fs::write(&path, "secret\n").unwrap();
+
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+    let res = auth::read_token(&path);
+    assert_eq!(res.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+    assert_eq!(auth::read_token(&path).unwrap(), "secret");
+}
+
+#[tokio::test]
+async fn server_client_tunnel() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    let dir = make_test_dir("server_client_tunnel");
+    let editor = tokio::net::UnixListener::bind(dir.join("editor.sock")).unwrap();
+    let mut prjcts = make_test_projects();
+    prjcts[0].socket = Some(dir.join("editor.sock"));
+    let (left, right) = streams::unix::pair().unwrap();
+    let mut server = Server::new(right, prjcts);
+    let mut client = Client::from(left);
+    let local = streams::unix::listen(dir.join("local.sock")).unwrap();
+
+    let echo = async {
+        let (mut stream, _) = editor.accept().await.unwrap();
+        let mut buf = [0; 64];
+        loop {
+            let len = stream.read(&mut buf).await.unwrap();
+            stream.write_all(&buf[..len]).await.unwrap();
+        }
+    };
+    let neovide = async {
+        let mut stream = tokio::net::UnixStream::connect(dir.join("local.sock"))
+            .await
+            .unwrap();
+        stream.write_all(b"ping").await.unwrap();
+        let mut buf = [0; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+    };
+    let forward = tunnel::forward(client.get_requester(), "/a/a/a", local);
+    tokio::select! {
+        _ = neovide => (),
+        _ = forward => panic!("Forwarding should never stop!"),
+        _ = echo => panic!("Editor should never stop!"),
+        _ = client.go_loop() => panic!("Client should never stop looping!"),
+        _ = server.next() => panic!("Server should never stop looping!"),
+    }
+}
+
+#[tokio::test]
+async fn server_client_tunnel_slow_editor() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    let dir = make_test_dir("server_client_tunnel_slow_editor");
+    let editor = tokio::net::UnixListener::bind(dir.join("editor.sock")).unwrap();
+    let mut prjcts = make_test_projects();
+    prjcts[0].socket = Some(dir.join("editor.sock"));
+    let (left, right) = streams::unix::pair().unwrap();
+    let mut server = Server::new(right, prjcts);
+    let mut client = Client::from(left);
+    let local = streams::unix::listen(dir.join("local.sock")).unwrap();
+    // More than queues and socket buffers on the way may hold
+    let sent: Vec<u8> = (0..16 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    let (stop_stalling, stalling) = tokio::sync::oneshot::channel();
+
+    let slow_editor = async {
+        let (mut stream, _) = editor.accept().await.unwrap();
+        stalling.await.unwrap();
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).await.unwrap();
+        assert!(received == sent, "Data is lost");
+    };
+    let neovide = async {
+        let mut stream = tokio::net::UnixStream::connect(dir.join("local.sock"))
+            .await
+            .unwrap();
+        let mut write = std::pin::pin!(stream.write_all(&sent));
+        let res = tokio::time::timeout(Duration::from_secs(1), &mut write).await;
+        assert!(res.is_err(), "Tunnel does not slow down writer");
+        stop_stalling.send(()).unwrap();
+        write.await.unwrap();
+        // Closes tunnel, the close follows all the data
+    };
+    let forward = tunnel::forward(client.get_requester(), "/a/a/a", local);
+    let transfer = async { join!(slow_editor, neovide) };
+    tokio::select! {
+        res = tokio::time::timeout(Duration::from_secs(10), transfer) => {
+            assert!(res.is_ok(), "Tunnel is not closed");
+        },
+        _ = forward => panic!("Forwarding should never stop!"),
+        _ = client.go_loop() => panic!("Client should never stop looping!"),
+        _ = server.next() => panic!("Server should never stop looping!"),
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn server_tunnel_to_idle_project() {
+    let (left, right) = streams::unix::pair().unwrap();
+    let mut server = Server::new(right, make_test_projects());
+    let mut client = Client::from(left);
+    let mut requester = client.get_requester();
+
+    tokio::select! {
+        res = requester.open_tunnel("/a/a/a") => {
+            assert_eq!(res.err().unwrap().kind(), io::ErrorKind::NotFound);
+        },
+        _ = client.go_loop() => panic!("Client should never stop looping!"),
+        _ = server.next() => panic!("Server should never stop looping!"),
+    }
+}
+
+#[tokio::test]
+async fn server_client_heartbeat() {
+    let (left, right) = streams::unix::pair().unwrap();
+    let heartbeat = streams::Heartbeat {
+        interval: Duration::from_millis(10),
+        max_missed: 1,
+    };
+    let mut server = Server::new(right, make_test_projects());
+    server.set_heartbeat(Some(heartbeat));
+    let mut client = Client::from(left);
+    client.set_heartbeat(Some(heartbeat));
+    let mut requester = client.get_requester();
+
+    tokio::select! {
+        res = async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            requester.list_projects().await
+        } => assert_eq!(res.unwrap(), make_test_projects()),
+        _ = client.go_loop() => panic!("Client should never stop looping!"),
+        _ = server.next() => panic!("Server should never stop looping!"),
+    }
+}
+
+#[tokio::test]
+async fn client_heartbeat_dead_server() {
+    let (left, _right) = streams::unix::pair().unwrap();
+    let mut client = Client::from(left);
+    client.set_heartbeat(Some(streams::Heartbeat {
+        interval: Duration::from_millis(10),
+        max_missed: 2,
+    }));
+    let mut requester = client.get_requester();
+
+    let (res, rsp) = join!(client.go_loop(), requester.list_projects());
+    assert!(matches!(res, Err(ProtocolError::NotResponding)));
+    assert_eq!(rsp.unwrap_err().kind(), io::ErrorKind::ConnectionAborted);
+}
+
+#[tokio::test]
+async fn client_reconnects_with_resync() {
+    use futures::StreamExt as _;
+    let path = make_socket_path("client_reconnects_with_resync");
+    let listener = streams::unix::listen(&path).unwrap();
+    let connect = || streams::unix::connect(&path);
+
+    let mut client = tokio::select! {
+        client = reconnect::ReconnectingClient::connect(connect, "test") => client.unwrap(),
+        _ = async {
+            let mut server = Server::new(listener.accept().await.unwrap(), make_test_projects());
+            server.next().await
+        } => panic!("Server should never stop looping!"),
+    };
+    drop(listener);
+    client.set_backoff(reconnect::Backoff {
+        initial: Duration::from_millis(10),
+        ..Default::default()
+    });
+    let mut updates = client.subscribe();
+
+    // Project "a" is deleted, "b" is served and "c" is created while down
+    let mut prjcts = make_test_projects();
+    prjcts.remove(0);
+    prjcts[0].socket = Some("/tmp/b.sock".into());
+    prjcts.push(ide::Project {
+        name: String::from("c"),
+        path: "/c/c/c".into(),
+        session_file: None,
+        exists: true,
+        socket: None,
+        last_modified: None,
+    });
+    let restarted = async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let listener = streams::unix::listen(&path).unwrap();
+        let mut server = Server::new(listener.accept().await.unwrap(), prjcts.clone());
+        server.next().await
+    };
+    tokio::select! {
+        received = updates.by_ref().take(3).collect::<Vec<_>>() => {
+            assert_eq!(received[0].delete_project().path, "/a/a/a");
+            let changed = received[1].project_state_changed();
+            assert_eq!(changed.path, "/b/b/b");
+            assert_eq!(changed.new_state, idep::project::State::PROJECT_SERVING.into());
+            assert_eq!(received[2].new_project().path, "/c/c/c");
+        },
+        _ = client.go_loop() => panic!("Client should never stop looping!"),
+        _ = restarted => panic!("Server should never stop looping!"),
+    }
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn servers_spawned_on_thread_pool() {
+    let context = Context::new(make_test_projects(), Default::default()).into_shared();
+    let mut clients = Vec::new();
+    for _ in 0..8 {
+        let (left, right) = streams::VirtualStreamBuilder::new_streams();
+        let mut server = Server::with_context(right, context.clone());
+        tokio::spawn(async move { server.next().await });
+        clients.push(tokio::spawn(async move {
+            let mut client = Client::connect(left.0, left.1, "test").await.unwrap();
+            let mut requester = client.get_requester();
+            tokio::select! {
+                res = requester.list_projects() => res.unwrap(),
+                _ = client.go_loop() => panic!("Client should never stop looping!"),
+            }
+        }));
+    }
+    for client in clients {
+        assert_eq!(client.await.unwrap(), make_test_projects());
+    }
+}
+
+#[tokio::test]
+async fn server_handles_requests_concurrently() {
+    let (left, right) = streams::VirtualStreamBuilder::new_streams();
+    let dir = make_test_dir("server_handles_requests_concurrently");
+    let path = dir.to_str().unwrap();
+    let mut prjcts = make_test_projects();
+    prjcts[0].path = dir.clone();
+
+    let mut client = Client::from(left);
+    let mut requester = client.get_requester();
+    let mut other_requester = client.get_requester();
+    let script = r#"sleep 0.3 && touch "$2" && exec sleep 10"#;
+    let headless = make_fake_headless(dir.join("sockets"), script);
+    let context = Context::new(prjcts, headless);
+    let mut server = Server::with_context(right, context.into_shared());
+
+    tokio::select! {
+        _ = async {
+            let mut serve = Box::pin(requester.serve_project(path));
+            tokio::select! {
+                res = other_requester.list_projects() => assert_eq!(res.unwrap().len(), 2),
+                _ = &mut serve => panic!("Server should wait for editor to listen!"),
+            }
+            assert!(serve.await.unwrap().exists());
+        } => (),
+        _ = client.go_loop() => panic!("Client should never stop looping!"),
+        _ = server.next() => panic!("Server should never stop looping!"),
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,133 @@
+use ide::protocol::*;
+use ide::registry::Registry;
+use std::path::PathBuf;
+
+mod common;
+use common::make_test_dir;
+
+fn make_project(path: &str) -> ide::Project {
+    ide::Project {
+        name: PathBuf::from(path)
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .into(),
+        path: path.into(),
+        session_file: None,
+        exists: true,
+        socket: None,
+        last_modified: None,
+    }
+}
+
+#[test]
+fn registry_save_load() {
+    let file = make_test_dir("registry_save_load").join("state/projects");
+    let mut registry = Registry::new(&file);
+    let mut prjcts = vec![make_project("/a/a/a"), make_project("/b/b/b")];
+    prjcts[1].socket = Some("/tmp/b.sock".into());
+    registry.update(&prjcts);
+    assert!(registry.set_tags("/a/a/a", vec!["rust".into()]));
+    assert!(!registry.set_tags("/c/c/c", vec![]));
+    registry.save().unwrap();
+    assert!(!file.with_extension("tmp").exists());
+
+    let loaded = Registry::load(&file).unwrap();
+    let entry = loaded.get("/a/a/a").unwrap();
+    assert_eq!(entry.name, "a");
+    assert_eq!(entry.tags, vec![String::from("rust")]);
+    assert_eq!(entry.last_opened, None);
+    let entry = loaded.get("/b/b/b").unwrap();
+    assert_eq!(entry.socket, Some("/tmp/b.sock".into()));
+    assert!(entry.last_opened.is_some());
+
+    registry.update(&prjcts[1..]);
+    assert!(registry.get("/a/a/a").is_none());
+}
+
+#[test]
+fn registry_merge() {
+    let dir = make_test_dir("registry_merge");
+    let mut registry = Registry::new(dir.join("projects"));
+    let mut registered = vec![make_project("/a/a/a"), make_project("/c/c/c")];
+    registered[1].socket = Some(dir.join("dead.sock"));
+    registry.update(&registered);
+
+    let mut session = make_project("/a/a/a");
+    session.session_file = Some("/sessions/__a__a__a".into());
+    let merged = registry.merge(vec![session.clone(), make_project("/b/b/b")]);
+    assert_eq!(merged.len(), 3);
+    assert_eq!(merged[0], session);
+    assert_eq!(merged[2].path, PathBuf::from("/c/c/c"));
+    assert!(!merged[2].exists);
+    assert!(merged[2].last_modified.is_some());
+    // Nobody listens on the socket anymore
+    assert!(!merged[2].is_serving());
+}
+
+#[test]
+fn registry_missing_file() {
+    let file = make_test_dir("registry_missing_file").join("projects");
+    let registry = Registry::load(&file).unwrap();
+    assert_eq!(registry.merge(vec![]), vec![]);
+}
+
+#[tokio::test]
+async fn server_persists_projects() {
+    let dir = make_test_dir("server_persists_projects");
+    let project_dir = dir.join("project");
+    std::fs::create_dir(&project_dir).unwrap();
+    let file = dir.join("projects");
+
+    let mut context = Context::new(vec![], Default::default());
+    context.set_registry(Registry::load(&file).unwrap());
+    let (left, right) = streams::VirtualStreamBuilder::new_streams();
+    let mut server = Server::with_context(right, context.into_shared());
+    let mut client = Client::from(left);
+    let mut requester = client.get_requester();
+
+    tokio::select! {
+        res = requester.new_project(project_dir.to_str().unwrap()) => {
+            res.unwrap();
+        },
+        _ = client.go_loop() => panic!("Client should never stop looping!"),
+        _ = server.next() => panic!("Server should never stop looping!"),
+    }
+    // Registry is saved in background
+    let mut projects = vec![];
+    for _ in 0..100 {
+        projects = Registry::load(&file).unwrap().merge(vec![]);
+        if !projects.is_empty() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert_eq!(projects.len(), 1);
+    assert_eq!(projects[0].path, project_dir);
+    assert_eq!(projects[0].name, "project");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn config_projects_by_recency() {
+    let dir = make_test_dir("config_projects_by_recency");
+    let sessions = dir.join("sessions");
+    std::fs::create_dir(&sessions).unwrap();
+    let saved = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1000);
+    for name in ["__a__a__a", "__b__b__b", "__c__c__c"] {
+        let file = std::fs::File::create(sessions.join(name)).unwrap();
+        file.set_modified(saved).unwrap();
+    }
+    let mut cfg = ide::Config::new();
+    cfg.set_folder(sessions.to_str().unwrap());
+    cfg.set_history_file(dir.join("history").to_str().unwrap());
+
+    let mut history = Registry::new(dir.join("history"));
+    history.mark_opened(&make_project("/b/b/b"));
+    history.save().unwrap();
+    let first = cfg.get_projects().unwrap().next().unwrap();
+    assert_eq!(first.path, PathBuf::from("/b/b/b"));
+    assert!(first.last_modified.is_some());
+    std::fs::remove_dir_all(dir).unwrap();
+}
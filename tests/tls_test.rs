@@ -0,0 +1,248 @@
+#![cfg(feature = "tls")]
+
+use futures::join;
+use ide::config::{File, Listen, RemoteServer};
+use ide::daemon;
+use ide::protocol::streams::tls;
+use ide::protocol::*;
+use ide::Config;
+use rcgen::{BasicConstraints, CertificateParams, IsCa, Issuer, KeyPair};
+use std::io;
+use std::os::unix::fs::PermissionsExt as _;
+
+mod common;
+use common::{make_test_dir, make_test_projects};
+
+/// Self-signed certificate authority.
+struct Authority {
+    issuer: Issuer<'static, KeyPair>,
+    pem: String,
+}
+
+impl Authority {
+    fn new() -> Self {
+        let key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let pem = params.self_signed(&key).unwrap().pem();
+        Self {
+            issuer: Issuer::new(params, key),
+            pem,
+        }
+    }
+
+    fn roots(&self) -> tls::Certificates {
+        tls::parse_certificates(self.pem.as_bytes()).unwrap()
+    }
+
+    fn issue(&self, name: &str) -> tls::Identity {
+        let (cert, key) = self.issue_pem(name);
+        tls::Identity::from_pem(cert.as_bytes(), key.as_bytes()).unwrap()
+    }
+
+    /// Returns PEM encoded certificate and private key.
+    fn issue_pem(&self, name: &str) -> (String, String) {
+        let key = KeyPair::generate().unwrap();
+        let params = CertificateParams::new(vec![name.to_string()]).unwrap();
+        let cert = params.signed_by(&key, &self.issuer).unwrap();
+        (cert.pem(), key.serialize_pem())
+    }
+}
+
+/// Accepts client and finishes TLS handshake with it.
+async fn accept(listener: &tls::Listener) -> io::Result<tls::TlsStream> {
+    let (handshake, _) = listener.accept().await?;
+    handshake.finish().await
+}
+
+#[tokio::test]
+async fn server_client_tls() {
+    let ca = Authority::new();
+    let config = tls::server_config(ca.issue("localhost"), None).unwrap();
+    let listener = tls::listen("127.0.0.1:0", config).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let prjcts = make_test_projects();
+
+    let config = tls::client_config(ca.roots(), None).unwrap();
+    let (client, server) = join!(tls::connect(addr, "localhost", config), accept(&listener));
+    let mut client = Client::from(client.unwrap());
+    let mut requester = client.get_requester();
+    let mut server = Server::new(server.unwrap(), prjcts.clone());
+
+    tokio::select! {
+        res = requester.list_projects() => assert_eq!(res.unwrap(), prjcts),
+        _ = client.go_loop() => panic!("Client should never stop looping!"),
+        _ = server.next() => panic!("Server should never stop looping!"),
+    }
+}
+
+#[tokio::test]
+async fn tls_untrusted_server() {
+    let ca = Authority::new();
+    let config = tls::server_config(ca.issue("localhost"), None).unwrap();
+    let listener = tls::listen("127.0.0.1:0", config).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let config = tls::client_config(Authority::new().roots(), None).unwrap();
+    let (client, _) = join!(tls::connect(addr, "localhost", config), accept(&listener));
+    assert!(client.is_err());
+}
+
+#[tokio::test]
+async fn tls_silent_client() {
+    let ca = Authority::new();
+    let config = tls::server_config(ca.issue("localhost"), None).unwrap();
+    let listener = tls::listen("127.0.0.1:0", config).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    // Accepting does not wait for handshake, which never happens here
+    let _silent = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let (handshake, _) = listener.accept().await.unwrap();
+    let timeout = std::time::Duration::from_millis(100);
+    let res = tokio::time::timeout(timeout, handshake.finish()).await;
+    assert!(res.is_err());
+
+    let config = tls::client_config(ca.roots(), None).unwrap();
+    let (client, server) = join!(tls::connect(addr, "localhost", config), accept(&listener));
+    assert!(client.is_ok() && server.is_ok());
+}
+
+#[tokio::test]
+async fn tls_client_certificate() {
+    let ca = Authority::new();
+    let config = tls::server_config(ca.issue("localhost"), Some(ca.roots())).unwrap();
+    let listener = tls::listen("127.0.0.1:0", config).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let config = tls::client_config(ca.roots(), None).unwrap();
+    let (_, server) = join!(tls::connect(addr, "localhost", config), accept(&listener));
+    assert!(server.is_err());
+
+    let config = tls::client_config(ca.roots(), Some(ca.issue("client"))).unwrap();
+    let (client, server) = join!(tls::connect(addr, "localhost", config), accept(&listener));
+    let mut client = Client::from(client.unwrap());
+    let mut requester = client.get_requester();
+    let mut server = Server::new(server.unwrap(), make_test_projects());
+
+    tokio::select! {
+        res = requester.list_projects() => assert_eq!(res.unwrap(), make_test_projects()),
+        _ = client.go_loop() => panic!("Client should never stop looping!"),
+        _ = server.next() => panic!("Server should never stop looping!"),
+    }
+}
+
+#[tokio::test]
+async fn daemon_serves_remote_clients() {
+    let dir = make_test_dir("daemon_serves_remote_clients");
+    let ca = Authority::new();
+    let (cert, key) = ca.issue_pem("localhost");
+    let listen = Listen {
+        address: String::from("127.0.0.1:0"),
+        cert_file: dir.join("server.pem"),
+        key_file: dir.join("server.key"),
+    };
+    std::fs::write(&listen.cert_file, cert).unwrap();
+    std::fs::write(&listen.key_file, key).unwrap();
+    let listener = daemon::listen_remote(&listen).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let mut context = Context::new(make_test_projects(), Default::default());
+    context.add_authenticator(auth::PeerUidAuthenticator::current_user());
+    context.add_authenticator(auth::TokenAuthenticator::new("secret"));
+    let connect = |token: &'static str| {
+        let config = tls::client_config(ca.roots(), None).unwrap();
+        async move {
+            let (s, r) = tls::connect(addr, "localhost", config).await.unwrap();
+            Client::connect_with_token(s, r, "test", token).await
+        }
+    };
+
+    tokio::select! {
+        _ = daemon::serve_remote(listener, context.into_shared()) => {
+            panic!("Server should never stop serving!")
+        },
+        _ = async {
+            // Clients which never finish handshake do not stop others
+            let _silent = tokio::net::TcpStream::connect(addr).await.unwrap();
+            // Remote clients are not of the same user, whatever they are
+            let client = connect("").await;
+            assert_eq!(client.err().unwrap().kind(), io::ErrorKind::PermissionDenied);
+
+            let mut client = connect("secret").await.unwrap();
+            let mut requester = client.get_requester();
+            tokio::select! {
+                res = requester.list_projects() => assert_eq!(res.unwrap(), make_test_projects()),
+                _ = client.go_loop() => panic!("Client should never stop looping!"),
+            }
+        } => (),
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn attach_to_remote_server() {
+    let dir = make_test_dir("attach_to_remote_server");
+    let ca = Authority::new();
+    let (cert, key) = ca.issue_pem("localhost");
+    let listen = Listen {
+        address: String::from("127.0.0.1:0"),
+        cert_file: dir.join("server.pem"),
+        key_file: dir.join("server.key"),
+    };
+    std::fs::write(&listen.cert_file, cert).unwrap();
+    std::fs::write(&listen.key_file, key).unwrap();
+    let listener = daemon::listen_remote(&listen).await.unwrap();
+
+    let project = ide::Project::from_path(dir.to_str().unwrap());
+    let headless = headless::Headless {
+        program: String::from("sh"),
+        args: vec![
+            String::from("-c"),
+            String::from(r#"touch "$2" && exec sleep 10"#),
+            String::from("fake-nvim"),
+        ],
+        sockets_folder: dir.join("remote"),
+        start_timeout: std::time::Duration::from_secs(5),
+    };
+    let mut context = Context::new(vec![project.clone()], headless.clone());
+    context.add_authenticator(auth::TokenAuthenticator::new("secret"));
+    let context = context.into_shared();
+
+    let server = RemoteServer {
+        name: String::from("workstation"),
+        address: format!("localhost:{}", listener.local_addr().unwrap().port()),
+        token_file: dir.join("token"),
+        ca_file: dir.join("ca.pem"),
+    };
+    std::fs::write(&server.token_file, "secret").unwrap();
+    let permissions = std::fs::Permissions::from_mode(0o600);
+    std::fs::set_permissions(&server.token_file, permissions).unwrap();
+    std::fs::write(&server.ca_file, &ca.pem).unwrap();
+    let file = File {
+        servers: vec![server],
+        ..Default::default()
+    };
+    let mut cfg = Config::with_file(file, &dir.join("config.toml"));
+    cfg.set_headless(headless::Headless {
+        sockets_folder: dir.join("local"),
+        ..headless
+    });
+    // Fails unless it is given the socket forwarded to the served project
+    let editor = dir.join("editor.sh");
+    let script = format!(
+        "#!/bin/sh\ncase \"$2\" in {}/attach-*) test -S \"$2\";; *) false;; esac\n",
+        dir.join("local").to_str().unwrap()
+    );
+    std::fs::write(&editor, script).unwrap();
+    std::fs::set_permissions(&editor, std::fs::Permissions::from_mode(0o755)).unwrap();
+    cfg.set_editor(editor.to_str().unwrap());
+
+    tokio::select! {
+        _ = daemon::serve_remote(listener, context.clone()) => {
+            panic!("Server should never stop serving!")
+        },
+        res = ide::attach::attach(&cfg, &project.name, Some("workstation")) => res.unwrap(),
+    }
+    assert!(context.lock().unwrap().projects()[0].is_serving());
+    std::fs::remove_dir_all(dir).unwrap();
+}
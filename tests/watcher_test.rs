@@ -0,0 +1,69 @@
+use futures::StreamExt as _;
+use ide::protocol::*;
+use ide::watcher::{Event, Watcher};
+use std::io;
+
+mod common;
+use common::make_test_dir;
+
+#[test]
+fn watcher_events() {
+    let dir = make_test_dir("watcher_events");
+    std::fs::write(dir.join("__tmp__old"), "").unwrap();
+    let mut watcher = Watcher::new(&dir).unwrap();
+    assert_eq!(
+        watcher.read().unwrap_err().kind(),
+        io::ErrorKind::WouldBlock
+    );
+
+    std::fs::write(dir.join("__tmp__old"), "").unwrap();
+    std::fs::write(dir.join("__tmp__new"), "").unwrap();
+    let events = watcher.read().unwrap();
+    assert_eq!(events.len(), 2);
+    assert!(matches!(&events[0], Event::Modified(prj) if prj.name == "old"));
+    assert!(matches!(&events[1], Event::Added(prj) if prj.name == "new"));
+    let added = ide::Project::from_session_file(dir.join("__tmp__new"));
+    assert_eq!(events[1], Event::Added(added));
+
+    std::fs::rename(dir.join("__tmp__new"), dir.join("__tmp__renamed")).unwrap();
+    std::fs::remove_file(dir.join("__tmp__old")).unwrap();
+    let events = watcher.read().unwrap();
+    assert!(matches!(&events[0], Event::Removed(prj) if prj.name == "new"));
+    assert!(matches!(&events[1], Event::Added(prj) if prj.name == "renamed"));
+    assert!(matches!(&events[2], Event::Removed(prj) if prj.name == "old"));
+    assert_eq!(events.len(), 3);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn server_watches_sessions() {
+    let (left, right) = streams::VirtualStreamBuilder::new_streams();
+    let dir = make_test_dir("server_watches_sessions");
+    let watcher = Watcher::new(&dir).unwrap();
+
+    let context = Context::new(vec![], Default::default()).into_shared();
+    let mut client = Client::from(left);
+    let mut requester = client.get_requester();
+    let mut updates = client.subscribe();
+    let mut server = Server::with_context(right, context.clone());
+
+    tokio::select! {
+        _ = async {
+            std::fs::write(dir.join("__tmp__a"), "").unwrap();
+            let upd = updates.next().await.unwrap();
+            assert_eq!(upd.new_project().path, "/tmp/a");
+            std::fs::write(dir.join("__tmp__a"), "").unwrap();
+            let projects = requester.list_projects().await.unwrap();
+            assert_eq!(projects.len(), 1);
+
+            std::fs::remove_file(dir.join("__tmp__a")).unwrap();
+            let upd = updates.next().await.unwrap();
+            assert_eq!(upd.delete_project().path, "/tmp/a");
+            assert!(requester.list_projects().await.unwrap().is_empty());
+        } => (),
+        _ = Context::watch_sessions(context.clone(), watcher) => panic!("Watcher should never stop!"),
+        _ = client.go_loop() => panic!("Client should never stop looping!"),
+        _ = server.next() => panic!("Server should never stop looping!"),
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}